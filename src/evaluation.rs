@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+use crate::pricing::PriceSource;
+use crate::steam::Inventory::{AssetDescription, Inventory};
+use crate::steam::offers::Offer;
+use crate::steam::Trade::{OfferSide, TradeOffer};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EvaluatorConfig {
+  // Applied to items we receive, below 1.0 means we value them under market
+  pub buy_margin: f64,
+  // Applied to items we give, above 1.0 means we value them over market
+  pub sell_margin: f64,
+  pub category_multipliers: Vec<CategoryMultiplier>,
+  // Their value divided by ours needed to accept, 1.0 is a 1:1 trade
+  pub min_ratio: f64,
+  // Ratios between this and min_ratio are worth countering
+  pub counter_ratio: f64,
+  pub decline_unpriced: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CategoryMultiplier {
+  pub category: String,
+  pub tag: String,
+  pub multiplier: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Recommendation {
+  Accept,
+  Decline,
+  Counter,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LineItem {
+  pub side: OfferSide,
+  pub assetid: String,
+  pub market_hash_name: Option<String>,
+  pub amount: u64,
+  pub unit_price: Option<u64>,
+  pub multiplier: f64,
+  pub value: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Evaluation {
+  pub our_value: u64,
+  pub their_value: u64,
+  pub ratio: Option<f64>,
+  pub shortfall: u64,
+  pub lines: Vec<LineItem>,
+  pub unpriced: Vec<String>,
+  pub recommendation: Recommendation,
+}
+
+pub struct Evaluator<'a> {
+  pub config: EvaluatorConfig,
+  pub prices: &'a dyn PriceSource,
+}
+
+struct PricedAsset {
+  side: OfferSide,
+  appid: i64,
+  assetid: String,
+  amount: u64,
+  description: Option<AssetDescription>,
+}
+
+impl EvaluatorConfig {
+  pub fn new() -> EvaluatorConfig {
+    EvaluatorConfig {
+      buy_margin: 1.0,
+      sell_margin: 1.0,
+      category_multipliers: Vec::new(),
+      min_ratio: 1.0,
+      counter_ratio: 0.9,
+      decline_unpriced: true,
+    }
+  }
+}
+
+impl<'a> Evaluator<'a> {
+  pub fn new(config: EvaluatorConfig, prices: &'a dyn PriceSource) -> Evaluator<'a> {
+    Evaluator { config, prices }
+  }
+
+  pub async fn evaluate_trade_offer(&self, trade_offer: &TradeOffer, self_inventory: &Inventory, partner_inventory: &Inventory) -> Evaluation {
+    let me = trade_offer.json_tradeoffer.me.assets.iter().map(|a| PricedAsset {
+      side: OfferSide::Me,
+      appid: a.appid.parse::<i64>().unwrap_or(0),
+      assetid: a.assetid.to_owned(),
+      amount: a.amount.parse::<u64>().unwrap_or(1),
+      description: self_inventory.get_description(a),
+    });
+
+    let them = trade_offer.json_tradeoffer.them.assets.iter().map(|a| PricedAsset {
+      side: OfferSide::Them,
+      appid: a.appid.parse::<i64>().unwrap_or(0),
+      assetid: a.assetid.to_owned(),
+      amount: a.amount.parse::<u64>().unwrap_or(1),
+      description: partner_inventory.get_description(a),
+    });
+
+    self.evaluate(me.chain(them).collect()).await
+  }
+
+  // Works on received offers fetched with descriptions
+  pub async fn evaluate_offer(&self, offer: &Offer) -> Evaluation {
+    let me = offer.items_to_give.iter().map(|i| (OfferSide::Me, i));
+    let them = offer.items_to_receive.iter().map(|i| (OfferSide::Them, i));
+
+    let assets = me.chain(them).map(|(side, i)| PricedAsset {
+      side,
+      appid: i.appid,
+      assetid: i.assetid.to_owned(),
+      amount: i.amount.parse::<u64>().unwrap_or(1),
+      description: i.description.to_owned(),
+    }).collect();
+
+    self.evaluate(assets).await
+  }
+
+  async fn evaluate(&self, assets: Vec<PricedAsset>) -> Evaluation {
+    let mut lines: Vec<LineItem> = Vec::new();
+    let mut unpriced: Vec<String> = Vec::new();
+    let mut our_value: u64 = 0;
+    let mut their_value: u64 = 0;
+
+    for asset in assets {
+      let market_hash_name = asset.description.as_ref().map(|d| d.market_hash_name.to_owned());
+
+      let unit_price = match &market_hash_name {
+        Some(name) => self.prices.price(asset.appid, name).await,
+        None => None,
+      };
+
+      let margin = match asset.side {
+        OfferSide::Me => self.config.sell_margin,
+        OfferSide::Them => self.config.buy_margin,
+      };
+      let multiplier = margin * self.category_multiplier(asset.description.as_ref());
+
+      let value = unit_price.map(|p| (p as f64 * asset.amount as f64 * multiplier).round() as u64);
+
+      match (asset.side, value) {
+        (OfferSide::Me, Some(v)) => our_value += v,
+        (OfferSide::Them, Some(v)) => their_value += v,
+        (_, None) => unpriced.push(asset.assetid.to_owned()),
+      }
+
+      lines.push(LineItem { side: asset.side, assetid: asset.assetid, market_hash_name, amount: asset.amount, unit_price, multiplier, value });
+    }
+
+    let ratio = match our_value {
+      0 => None,
+      ours => Some(their_value as f64 / ours as f64),
+    };
+
+    // The slack stops ratios like 1.1 turning 100 cents into a requirement of 111
+    let required = (our_value as f64 * self.config.min_ratio - 1e-6).ceil() as u64;
+    let shortfall = required.saturating_sub(their_value);
+
+    let gives_items = lines.iter().any(|l| l.side == OfferSide::Me);
+    let gives_unpriced = lines.iter().any(|l| l.side == OfferSide::Me && l.value.is_none());
+
+    // Something of ours we cannot price could be worth anything, so it is never given away
+    let recommendation = if gives_unpriced || (!unpriced.is_empty() && self.config.decline_unpriced) {
+      Recommendation::Decline
+    } else {
+      match ratio {
+        None if !gives_items => Recommendation::Accept,
+        None => Recommendation::Decline,
+        Some(_) if shortfall == 0 => Recommendation::Accept,
+        Some(r) if r >= self.config.counter_ratio => Recommendation::Counter,
+        Some(_) => Recommendation::Decline,
+      }
+    };
+
+    Evaluation { our_value, their_value, ratio, shortfall, lines, unpriced, recommendation }
+  }
+
+  fn category_multiplier(&self, description: Option<&AssetDescription>) -> f64 {
+    let description = match description {
+      Some(d) => d,
+      None => return 1.0,
+    };
+
+    self.config.category_multipliers.iter()
+      .filter(|m| description.tags.iter().any(|t| {
+        (t.category == m.category || t.localized_category_name == m.category) &&
+        (t.localized_tag_name == m.tag || t.internal_name == m.tag)
+      }))
+      .map(|m| m.multiplier)
+      .product()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::pricing::StaticPriceSource;
+  use crate::steam::offers::OfferItem;
+  use crate::test_support::{description, offer, offer_item};
+
+  fn prices() -> StaticPriceSource {
+    let mut prices = StaticPriceSource::new();
+    prices.insert(730, "AK-47 | Redline (Field-Tested)".to_string(), 1000);
+    prices.insert(730, "Mann Co. Supply Crate Key".to_string(), 250);
+    prices.insert(730, "Sticker | Crown (Foil)".to_string(), 100);
+    prices
+  }
+
+  fn item(assetid: &str, name: &str, tags: &[(&str, &str)]) -> OfferItem {
+    offer_item(assetid, Some(description(assetid, name, tags)))
+  }
+
+  fn redline(assetid: &str) -> OfferItem {
+    item(assetid, "AK-47 | Redline (Field-Tested)", &[("Type", "Rifle")])
+  }
+
+  fn key(assetid: &str) -> OfferItem {
+    item(assetid, "Mann Co. Supply Crate Key", &[("Type", "Key")])
+  }
+
+  fn knife(assetid: &str) -> OfferItem {
+    item(assetid, "★ Karambit | Fade (Factory New)", &[("Type", "Knife")])
+  }
+
+  fn lenient() -> EvaluatorConfig {
+    let mut config = EvaluatorConfig::new();
+    config.decline_unpriced = false;
+    config
+  }
+
+  #[tokio::test]
+  async fn balanced_offer_is_accepted() {
+    let prices = prices();
+    let evaluator = Evaluator::new(EvaluatorConfig::new(), &prices);
+
+    let evaluation = evaluator.evaluate_offer(&offer(vec![redline("1")], vec![key("10"), key("11"), key("12"), key("13")])).await;
+
+    assert_eq!(evaluation.our_value, 1000);
+    assert_eq!(evaluation.their_value, 1000);
+    assert_eq!(evaluation.ratio, Some(1.0));
+    assert_eq!(evaluation.shortfall, 0);
+    assert_eq!(evaluation.lines.len(), 5);
+    assert_eq!(evaluation.recommendation, Recommendation::Accept);
+  }
+
+  #[tokio::test]
+  async fn near_miss_is_countered_and_far_miss_declined() {
+    let prices = prices();
+    let evaluator = Evaluator::new(EvaluatorConfig::new(), &prices);
+
+    let near = evaluator.evaluate_offer(&offer(vec![redline("1")], vec![key("10"), key("11"), key("12"), item("13", "Sticker | Crown (Foil)", &[])])).await;
+    assert_eq!(near.their_value, 850);
+    assert_eq!(near.recommendation, Recommendation::Decline);
+
+    let mut config = EvaluatorConfig::new();
+    config.counter_ratio = 0.8;
+    let evaluator = Evaluator::new(config, &prices);
+
+    let near = evaluator.evaluate_offer(&offer(vec![redline("1")], vec![key("10"), key("11"), key("12"), item("13", "Sticker | Crown (Foil)", &[])])).await;
+    assert_eq!(near.shortfall, 150);
+    assert_eq!(near.recommendation, Recommendation::Counter);
+  }
+
+  #[tokio::test]
+  async fn margins_and_category_multipliers() {
+    let prices = prices();
+    let mut config = EvaluatorConfig::new();
+    config.buy_margin = 0.9;
+    config.sell_margin = 1.1;
+    config.category_multipliers.push(CategoryMultiplier { category: "Type".to_string(), tag: "Key".to_string(), multiplier: 1.2 });
+    let evaluator = Evaluator::new(config, &prices);
+
+    let evaluation = evaluator.evaluate_offer(&offer(vec![redline("1")], vec![key("10")])).await;
+
+    assert_eq!(evaluation.our_value, 1100);
+    assert_eq!(evaluation.their_value, 270);
+    assert_eq!(evaluation.lines[1].multiplier, 0.9 * 1.2);
+  }
+
+  #[tokio::test]
+  async fn min_ratio_needs_no_extra_cent() {
+    let prices = prices();
+    let mut config = EvaluatorConfig::new();
+    config.min_ratio = 1.1;
+    let evaluator = Evaluator::new(config, &prices);
+
+    let evaluation = evaluator.evaluate_offer(&offer(vec![redline("1")], vec![redline("10"), item("11", "Sticker | Crown (Foil)", &[])])).await;
+
+    assert_eq!(evaluation.shortfall, 0);
+    assert_eq!(evaluation.recommendation, Recommendation::Accept);
+  }
+
+  #[tokio::test]
+  async fn donations_are_accepted() {
+    let prices = prices();
+    let evaluator = Evaluator::new(lenient(), &prices);
+
+    let evaluation = evaluator.evaluate_offer(&offer(vec![], vec![knife("10")])).await;
+
+    assert_eq!(evaluation.ratio, None);
+    assert_eq!(evaluation.unpriced, vec!["10".to_string()]);
+    assert_eq!(evaluation.recommendation, Recommendation::Accept);
+  }
+
+  #[tokio::test]
+  async fn unpriced_items_of_ours_are_never_given_away() {
+    let prices = prices();
+    let evaluator = Evaluator::new(lenient(), &prices);
+
+    let for_nothing = evaluator.evaluate_offer(&offer(vec![knife("1")], vec![])).await;
+    assert_eq!(for_nothing.our_value, 0);
+    assert_eq!(for_nothing.recommendation, Recommendation::Decline);
+
+    let overpaid = evaluator.evaluate_offer(&offer(vec![knife("1"), item("2", "Sticker | Crown (Foil)", &[])], vec![redline("10")])).await;
+    assert_eq!(overpaid.recommendation, Recommendation::Decline);
+
+    let undescribed = evaluator.evaluate_offer(&offer(vec![offer_item("1", None)], vec![redline("10")])).await;
+    assert_eq!(undescribed.recommendation, Recommendation::Decline);
+  }
+
+  #[tokio::test]
+  async fn unpriced_items_of_theirs_follow_config() {
+    let prices = prices();
+    let offer = offer(vec![key("1")], vec![redline("10"), knife("11")]);
+
+    let strict = Evaluator::new(EvaluatorConfig::new(), &prices).evaluate_offer(&offer).await;
+    assert_eq!(strict.recommendation, Recommendation::Decline);
+
+    let lenient = Evaluator::new(lenient(), &prices).evaluate_offer(&offer).await;
+    assert_eq!(lenient.recommendation, Recommendation::Accept);
+  }
+
+  #[tokio::test]
+  async fn evaluates_trade_offers_against_inventories() {
+    let prices = prices();
+    let evaluator = Evaluator::new(EvaluatorConfig::new(), &prices);
+
+    let ours = crate::test_support::inventory(vec![("1", description("c1", "AK-47 | Redline (Field-Tested)", &[]))]);
+    let theirs = crate::test_support::inventory(vec![("10", description("c2", "Mann Co. Supply Crate Key", &[]))]);
+
+    let mut trade_offer = TradeOffer::new_with_steam_id(crate::steam::steam_id::SteamId::from_account_id(22202), None);
+    trade_offer.add_self_item(crate::steam::Trade::OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), "1".to_string()));
+    trade_offer.add_partner_item(crate::steam::Trade::OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), "10".to_string()));
+
+    let evaluation = evaluator.evaluate_trade_offer(&trade_offer, &ours, &theirs).await;
+
+    assert_eq!(evaluation.our_value, 1000);
+    assert_eq!(evaluation.their_value, 250);
+    assert_eq!(evaluation.recommendation, Recommendation::Decline);
+  }
+}
@@ -0,0 +1,859 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+use crate::evaluation::Evaluation;
+use crate::steam::bulk_trade::{BulkTrade, BulkTradeProgress};
+use crate::steam::Inventory::Inventory;
+use crate::steam::offers::{Offer, OfferError, TradeOfferAccepted};
+use crate::steam::offer_state::{StateChange, TradeOfferState, TradeOfferStateTracker};
+use crate::steam::steam_id::SteamId;
+use crate::steam::trade_history::TradeReceipt;
+use crate::steam::Trade::{IdempotentSendError, OfferSide, SendKeyRecord, SendKeyStore, TradeOffer, TradeOfferSuccess};
+
+// Each entry moves the schema one version forward, never edit an applied one
+const MIGRATIONS: [&str; 3] = [
+  "CREATE TABLE offers (
+    tradeofferid TEXT PRIMARY KEY,
+    direction TEXT NOT NULL,
+    action TEXT NOT NULL,
+    partner TEXT NOT NULL,
+    message TEXT NOT NULL,
+    state INTEGER,
+    tradeid TEXT,
+    our_value INTEGER,
+    their_value INTEGER,
+    recorded_at INTEGER NOT NULL
+  );
+  CREATE INDEX offers_partner ON offers (partner);
+  CREATE INDEX offers_recorded_at ON offers (recorded_at);
+  CREATE TABLE offer_items (
+    tradeofferid TEXT NOT NULL REFERENCES offers (tradeofferid),
+    side TEXT NOT NULL,
+    appid INTEGER NOT NULL,
+    contextid TEXT NOT NULL,
+    assetid TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    market_hash_name TEXT,
+    value INTEGER,
+    new_contextid TEXT,
+    new_assetid TEXT
+  );
+  CREATE INDEX offer_items_offer ON offer_items (tradeofferid);
+  CREATE INDEX offer_items_name ON offer_items (market_hash_name);
+  CREATE INDEX offer_items_assetid ON offer_items (assetid);
+  CREATE TABLE state_transitions (
+    tradeofferid TEXT NOT NULL REFERENCES offers (tradeofferid),
+    from_state INTEGER,
+    to_state INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    legal INTEGER NOT NULL
+  );
+  CREATE INDEX state_transitions_offer ON state_transitions (tradeofferid);",
+  "CREATE TABLE send_keys (
+    key TEXT PRIMARY KEY,
+    partner TEXT NOT NULL,
+    assets TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    success TEXT
+  );",
+  "ALTER TABLE offers ADD COLUMN tradeofferid_countered TEXT;
+  CREATE INDEX offers_countered ON offers (tradeofferid_countered);",
+];
+
+pub struct Ledger {
+  conn: Mutex<Connection>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum LedgerDirection {
+  Sent,
+  Received,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum LedgerAction {
+  Sent,
+  Accepted,
+  Declined,
+  Canceled,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LedgerEntry {
+  pub tradeofferid: String,
+  pub direction: LedgerDirection,
+  pub action: LedgerAction,
+  pub partner: SteamId,
+  pub message: String,
+  pub state: Option<TradeOfferState>,
+  pub tradeid: Option<String>,
+  pub our_value: Option<u64>,
+  pub their_value: Option<u64>,
+  pub recorded_at: u64,
+  // The offer this one countered, kept here so the link survives a restart
+  pub tradeofferid_countered: Option<String>,
+  pub items: Vec<LedgerItem>,
+  pub transitions: Vec<StateChange>,
+}
+
+// Steam has already acted when a ledger write fails, so its outcome is handed back with the error
+#[derive(Debug)]
+pub enum RecordError<T, E> {
+  Steam(E),
+  Ledger(T, rusqlite::Error),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LedgerItem {
+  pub side: OfferSide,
+  pub appid: i64,
+  pub contextid: String,
+  pub assetid: String,
+  pub amount: String,
+  pub market_hash_name: Option<String>,
+  pub value: Option<u64>,
+  pub new_contextid: Option<String>,
+  pub new_assetid: Option<String>,
+}
+
+impl Ledger {
+  pub fn open(path: &str) -> Result<Ledger> {
+    Ledger::init(Connection::open(path)?)
+  }
+
+  pub fn open_in_memory() -> Result<Ledger> {
+    Ledger::init(Connection::open_in_memory()?)
+  }
+
+  fn init(mut conn: Connection) -> Result<Ledger> {
+    migrate(&mut conn, &MIGRATIONS)?;
+    Ok(Ledger { conn: Mutex::new(conn) })
+  }
+
+  // Names come from the inventories the items were picked from, values from the evaluation when there is one
+  pub fn record_sent(&self, trade_offer: &TradeOffer, success: &TradeOfferSuccess, inventories: &[&Inventory], evaluation: Option<&Evaluation>) -> Result<()> {
+    let lines = evaluation_lines(evaluation);
+    let names = inventory_names(inventories);
+
+    let items = trade_offer.json_tradeoffer.me.assets.iter().map(|a| (OfferSide::Me, a))
+      .chain(trade_offer.json_tradeoffer.them.assets.iter().map(|a| (OfferSide::Them, a)))
+      .map(|(side, a)| {
+        let line = lines.get(&a.assetid);
+        let appid = a.appid.parse::<i64>().unwrap_or(0);
+        let name = names.get(&(appid, a.contextid.as_str(), a.assetid.as_str()));
+
+        LedgerItem {
+          side,
+          appid,
+          contextid: a.contextid.to_owned(),
+          assetid: a.assetid.to_owned(),
+          amount: a.amount.to_owned(),
+          market_hash_name: name.map(|n| n.to_string()).or_else(|| line.and_then(|l| l.0.to_owned())),
+          value: line.and_then(|l| l.1),
+          new_contextid: None,
+          new_assetid: None,
+        }
+      }).collect::<Vec<LedgerItem>>();
+
+    let entry = LedgerEntry {
+      tradeofferid: success.tradeofferid.to_owned(),
+      direction: LedgerDirection::Sent,
+      action: LedgerAction::Sent,
+      partner: trade_offer.partner,
+      message: trade_offer.tradeoffermessage.to_owned(),
+      state: None,
+      tradeid: None,
+      our_value: evaluation.map(|e| e.our_value),
+      their_value: evaluation.map(|e| e.their_value),
+      recorded_at: now(),
+      tradeofferid_countered: trade_offer.tradeofferid_countered.to_owned(),
+      items,
+      transitions: Vec::new(),
+    };
+
+    self.insert(&entry)
+  }
+
+  pub fn record_received(&self, offer: &Offer, action: LedgerAction, evaluation: Option<&Evaluation>) -> Result<()> {
+    let lines = evaluation_lines(evaluation);
+
+    let items = offer.items_to_give.iter().map(|i| (OfferSide::Me, i))
+      .chain(offer.items_to_receive.iter().map(|i| (OfferSide::Them, i)))
+      .map(|(side, i)| LedgerItem {
+        side,
+        appid: i.appid,
+        contextid: i.contextid.to_owned(),
+        assetid: i.assetid.to_owned(),
+        amount: i.amount.to_owned(),
+        market_hash_name: i.description.as_ref().map(|d| d.market_hash_name.to_owned()),
+        value: lines.get(&i.assetid).and_then(|l| l.1),
+        new_contextid: None,
+        new_assetid: None,
+      }).collect::<Vec<LedgerItem>>();
+
+    let entry = LedgerEntry {
+      tradeofferid: offer.tradeofferid.to_owned(),
+      direction: match offer.is_our_offer {
+        true => LedgerDirection::Sent,
+        false => LedgerDirection::Received,
+      },
+      action,
+      partner: offer.partner,
+      message: offer.message.to_owned(),
+      state: Some(offer.state),
+      tradeid: offer.tradeid.to_owned(),
+      our_value: evaluation.map(|e| e.our_value),
+      their_value: evaluation.map(|e| e.their_value),
+      recorded_at: now(),
+      tradeofferid_countered: None,
+      items,
+      transitions: Vec::new(),
+    };
+
+    self.insert(&entry)
+  }
+
+  // Puts the recorded counter links back into a fresh tracker, e.g. after a restart
+  pub fn restore_counters(&self, tracker: &mut TradeOfferStateTracker) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+
+    let mut stmt = conn.prepare("SELECT tradeofferid_countered, tradeofferid FROM offers WHERE tradeofferid_countered IS NOT NULL")?;
+    let links = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+
+    for link in links {
+      let (original, counter) = link?;
+      tracker.record_counter(&original, &counter);
+    }
+
+    Ok(())
+  }
+
+  pub fn record_state(&self, tradeofferid: &String, change: &StateChange) -> Result<()> {
+    let conn = self.conn.lock().unwrap();
+
+    conn.execute(
+      "INSERT INTO state_transitions (tradeofferid, from_state, to_state, time, legal) VALUES (?1, ?2, ?3, ?4, ?5)",
+      params![tradeofferid, change.from.map(|s| s.to_i32()), change.to.to_i32(), change.time as i64, change.legal],
+    )?;
+    conn.execute("UPDATE offers SET state = ?2 WHERE tradeofferid = ?1", params![tradeofferid, change.to.to_i32()])?;
+
+    Ok(())
+  }
+
+  pub fn record_receipt(&self, tradeofferid: &String, receipt: &TradeReceipt) -> Result<()> {
+    let mut conn = self.conn.lock().unwrap();
+    let tx = conn.transaction()?;
+
+    tx.execute("UPDATE offers SET tradeid = ?2 WHERE tradeofferid = ?1", params![tradeofferid, receipt.tradeid])?;
+
+    for (side, assets) in [("Me", &receipt.assets_given), ("Them", &receipt.assets_received)] {
+      for asset in assets {
+        tx.execute(
+          "UPDATE offer_items SET new_contextid = ?4, new_assetid = ?5 WHERE tradeofferid = ?1 AND side = ?2 AND assetid = ?3",
+          params![tradeofferid, side, asset.assetid, asset.new_contextid, asset.new_assetid],
+        )?;
+      }
+    }
+
+    tx.commit()
+  }
+
+  pub fn contains(&self, tradeofferid: &String) -> Result<bool> {
+    let conn = self.conn.lock().unwrap();
+    conn.query_row("SELECT 1 FROM offers WHERE tradeofferid = ?1", params![tradeofferid], |_| Ok(())).optional().map(|r| r.is_some())
+  }
+
+  pub fn get(&self, tradeofferid: &String) -> Result<Option<LedgerEntry>> {
+    let conn = self.conn.lock().unwrap();
+
+    let entry = conn.query_row(
+      "SELECT * FROM offers WHERE tradeofferid = ?1",
+      params![tradeofferid],
+      entry_from_row,
+    ).optional()?;
+
+    match entry {
+      Some(entry) => Ok(Some(load_details(&conn, entry)?)),
+      None => Ok(None),
+    }
+  }
+
+  pub fn by_partner(&self, partner: &SteamId) -> Result<Vec<LedgerEntry>> {
+    self.query("SELECT * FROM offers WHERE partner = ?1 ORDER BY recorded_at", params![partner.to_string()])
+  }
+
+  // Matches a market_hash_name, an original assetid or the assetid it became after the trade
+  pub fn by_item(&self, item: &str) -> Result<Vec<LedgerEntry>> {
+    self.query(
+      "SELECT * FROM offers WHERE tradeofferid IN (
+        SELECT tradeofferid FROM offer_items WHERE market_hash_name = ?1 OR assetid = ?1 OR new_assetid = ?1
+      ) ORDER BY recorded_at",
+      params![item],
+    )
+  }
+
+  pub fn by_date_range(&self, from: u64, to: u64) -> Result<Vec<LedgerEntry>> {
+    self.query("SELECT * FROM offers WHERE recorded_at >= ?1 AND recorded_at < ?2 ORDER BY recorded_at", params![from as i64, to as i64])
+  }
+
+  fn query<P: rusqlite::Params>(&self, sql: &str, params: P) -> Result<Vec<LedgerEntry>> {
+    let conn = self.conn.lock().unwrap();
+
+    let entries = {
+      let mut stmt = conn.prepare(sql)?;
+      let rows = stmt.query_map(params, entry_from_row)?;
+      rows.collect::<Result<Vec<LedgerEntry>>>()?
+    };
+
+    entries.into_iter().map(|e| load_details(&conn, e)).collect()
+  }
+
+  // Recording an offer again keeps what record_state and record_receipt learned since
+  fn insert(&self, entry: &LedgerEntry) -> Result<()> {
+    let mut conn = self.conn.lock().unwrap();
+    let tx = conn.transaction()?;
+
+    tx.execute(
+      "INSERT INTO offers (tradeofferid, direction, action, partner, message, state, tradeid, our_value, their_value, recorded_at, tradeofferid_countered)
+       VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+       ON CONFLICT(tradeofferid) DO UPDATE SET
+         direction = excluded.direction,
+         action = excluded.action,
+         partner = excluded.partner,
+         message = excluded.message,
+         state = COALESCE(excluded.state, offers.state),
+         tradeid = COALESCE(excluded.tradeid, offers.tradeid),
+         our_value = COALESCE(excluded.our_value, offers.our_value),
+         their_value = COALESCE(excluded.their_value, offers.their_value),
+         recorded_at = excluded.recorded_at,
+         tradeofferid_countered = COALESCE(excluded.tradeofferid_countered, offers.tradeofferid_countered)",
+      params![
+        entry.tradeofferid,
+        format!("{:?}", entry.direction),
+        format!("{:?}", entry.action),
+        entry.partner.to_string(),
+        entry.message,
+        entry.state.map(|s| s.to_i32()),
+        entry.tradeid,
+        entry.our_value.map(|v| v as i64),
+        entry.their_value.map(|v| v as i64),
+        entry.recorded_at as i64,
+        entry.tradeofferid_countered,
+      ],
+    )?;
+
+    let received = {
+      let mut stmt = tx.prepare("SELECT side, assetid, new_contextid, new_assetid FROM offer_items WHERE tradeofferid = ?1 AND new_assetid IS NOT NULL")?;
+      let rows = stmt.query_map(params![entry.tradeofferid], |row| Ok(((row.get::<_, String>(0)?, row.get::<_, String>(1)?), (row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?))))?;
+      rows.collect::<Result<HashMap<(String, String), (Option<String>, Option<String>)>>>()?
+    };
+
+    tx.execute("DELETE FROM offer_items WHERE tradeofferid = ?1", params![entry.tradeofferid])?;
+
+    for item in &entry.items {
+      let (new_contextid, new_assetid) = match received.get(&(format!("{:?}", item.side), item.assetid.to_owned())) {
+        Some(ids) if item.new_assetid.is_none() => ids.to_owned(),
+        _ => (item.new_contextid.to_owned(), item.new_assetid.to_owned()),
+      };
+
+      tx.execute(
+        "INSERT INTO offer_items (tradeofferid, side, appid, contextid, assetid, amount, market_hash_name, value, new_contextid, new_assetid)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+          entry.tradeofferid,
+          format!("{:?}", item.side),
+          item.appid,
+          item.contextid,
+          item.assetid,
+          item.amount,
+          item.market_hash_name,
+          item.value.map(|v| v as i64),
+          new_contextid,
+          new_assetid,
+        ],
+      )?;
+    }
+
+    tx.commit()
+  }
+}
+
+impl SendKeyStore for Ledger {
+  fn get_send_key(&self, key: &str) -> std::result::Result<Option<SendKeyRecord>, String> {
+    let conn = self.conn.lock().unwrap();
+
+    let row = conn.query_row(
+      "SELECT partner, assets, created_at, success FROM send_keys WHERE key = ?1",
+      params![key],
+      |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, Option<String>>(3)?)),
+    ).optional().map_err(|e| e.to_string())?;
+
+    let (partner, assets, created_at, success) = match row {
+      Some(row) => row,
+      None => return Ok(None),
+    };
+
+    let success = match success {
+      Some(success) => Some(serde_json::from_str::<TradeOfferSuccess>(&success).map_err(|e| e.to_string())?),
+      None => None,
+    };
+
+    Ok(Some(SendKeyRecord {
+      key: key.to_owned(),
+      partner: SteamId::parse(&partner).map_err(|e| e.to_string())?,
+      assets: serde_json::from_str::<Vec<String>>(&assets).map_err(|e| e.to_string())?,
+      created_at: created_at as u64,
+      success,
+    }))
+  }
+
+  fn begin_send_key(&self, record: &SendKeyRecord) -> std::result::Result<(), String> {
+    let conn = self.conn.lock().unwrap();
+    let assets = serde_json::to_string(&record.assets).map_err(|e| e.to_string())?;
+
+    conn.execute(
+      "INSERT INTO send_keys (key, partner, assets, created_at) VALUES (?1, ?2, ?3, ?4)",
+      params![record.key, record.partner.to_string(), assets, record.created_at as i64],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(())
+  }
+
+  fn complete_send_key(&self, key: &str, success: &TradeOfferSuccess) -> std::result::Result<(), String> {
+    let conn = self.conn.lock().unwrap();
+    let success = serde_json::to_string(success).map_err(|e| e.to_string())?;
+
+    conn.execute("UPDATE send_keys SET success = ?2 WHERE key = ?1", params![key, success]).map_err(|e| e.to_string())?;
+
+    Ok(())
+  }
+}
+
+pub async fn send_and_record(ledger: &Ledger, trade_offer: &mut TradeOffer, cookie: &String, inventories: &[&Inventory], evaluation: Option<&Evaluation>) -> std::result::Result<TradeOfferSuccess, RecordError<TradeOfferSuccess, OfferError>> {
+  let success = trade_offer.send(cookie).await.map_err(RecordError::Steam)?;
+
+  match ledger.record_sent(trade_offer, &success, inventories, evaluation) {
+    Ok(()) => Ok(success),
+    Err(e) => Err(RecordError::Ledger(success, e)),
+  }
+}
+
+// Retrying after a ledger failure is safe, the key returns the offer that was already sent and recording runs again
+pub async fn send_idempotent_and_record(ledger: &Ledger, trade_offer: &mut TradeOffer, cookie: &String, api_key: &String, key: &str, inventories: &[&Inventory], evaluation: Option<&Evaluation>) -> std::result::Result<TradeOfferSuccess, RecordError<TradeOfferSuccess, IdempotentSendError>> {
+  let success = trade_offer.send_idempotent(cookie, api_key, key, ledger).await.map_err(RecordError::Steam)?;
+
+  match ledger.record_sent(trade_offer, &success, inventories, evaluation) {
+    Ok(()) => Ok(success),
+    Err(e) => Err(RecordError::Ledger(success, e)),
+  }
+}
+
+// Uses the ledger as the key store and records every sent chunk it does not know yet,
+// which also picks up chunks whose recording failed on an earlier run
+pub async fn send_bulk_and_record(ledger: &Ledger, bulk: &mut BulkTrade, cookie: &String, api_key: &String, inventories: &[&Inventory]) -> std::result::Result<BulkTradeProgress, RecordError<BulkTradeProgress, IdempotentSendError>> {
+  let sent = bulk.send(cookie, api_key, ledger).await;
+
+  for chunk in &bulk.chunks {
+    let success = match &chunk.sent {
+      Some(success) => success,
+      None => continue,
+    };
+
+    let recorded = ledger.contains(&success.tradeofferid)
+      .and_then(|known| if known { Ok(()) } else { ledger.record_sent(&chunk.offer, success, inventories, None) });
+
+    if let Err(e) = recorded {
+      return Err(RecordError::Ledger(bulk.progress(), e));
+    }
+  }
+
+  sent.map_err(RecordError::Steam)
+}
+
+pub async fn accept_and_record(ledger: &Ledger, offer: &Offer, cookie: &String, evaluation: Option<&Evaluation>) -> std::result::Result<TradeOfferAccepted, RecordError<TradeOfferAccepted, OfferError>> {
+  let accepted = offer.accept(cookie).await.map_err(RecordError::Steam)?;
+
+  match ledger.record_received(offer, LedgerAction::Accepted, evaluation) {
+    Ok(()) => Ok(accepted),
+    Err(e) => Err(RecordError::Ledger(accepted, e)),
+  }
+}
+
+pub async fn decline_and_record(ledger: &Ledger, offer: &Offer, cookie: &String, evaluation: Option<&Evaluation>) -> std::result::Result<(), RecordError<(), OfferError>> {
+  offer.decline(cookie).await.map_err(RecordError::Steam)?;
+
+  match ledger.record_received(offer, LedgerAction::Declined, evaluation) {
+    Ok(()) => Ok(()),
+    Err(e) => Err(RecordError::Ledger((), e)),
+  }
+}
+
+// Applies the migrations past the database's user_version, one transaction each
+pub(crate) fn migrate(conn: &mut Connection, migrations: &[&str]) -> Result<()> {
+  let version = conn.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))? as usize;
+
+  for (index, migration) in migrations.iter().enumerate().skip(version) {
+    let tx = conn.transaction()?;
+    tx.execute_batch(migration)?;
+    tx.pragma_update(None, "user_version", (index + 1) as i64)?;
+    tx.commit()?;
+  }
+
+  Ok(())
+}
+
+fn entry_from_row(row: &Row) -> Result<LedgerEntry> {
+  let direction = match row.get::<_, String>("direction")?.as_str() {
+    "Sent" => LedgerDirection::Sent,
+    _ => LedgerDirection::Received,
+  };
+
+  let action = match row.get::<_, String>("action")?.as_str() {
+    "Accepted" => LedgerAction::Accepted,
+    "Declined" => LedgerAction::Declined,
+    "Canceled" => LedgerAction::Canceled,
+    _ => LedgerAction::Sent,
+  };
+
+  let partner = row.get::<_, String>("partner")?;
+  let partner = match SteamId::parse(&partner) {
+    Ok(id) => id,
+    Err(e) => return Err(rusqlite::Error::InvalidColumnType(0, e.to_string(), rusqlite::types::Type::Text)),
+  };
+
+  Ok(LedgerEntry {
+    tradeofferid: row.get("tradeofferid")?,
+    direction,
+    action,
+    partner,
+    message: row.get("message")?,
+    state: row.get::<_, Option<i32>>("state")?.map(TradeOfferState::from_i32),
+    tradeid: row.get("tradeid")?,
+    our_value: row.get::<_, Option<i64>>("our_value")?.map(|v| v as u64),
+    their_value: row.get::<_, Option<i64>>("their_value")?.map(|v| v as u64),
+    recorded_at: row.get::<_, i64>("recorded_at")? as u64,
+    tradeofferid_countered: row.get("tradeofferid_countered")?,
+    items: Vec::new(),
+    transitions: Vec::new(),
+  })
+}
+
+fn load_details(conn: &Connection, mut entry: LedgerEntry) -> Result<LedgerEntry> {
+  let mut stmt = conn.prepare(
+    "SELECT side, appid, contextid, assetid, amount, market_hash_name, value, new_contextid, new_assetid FROM offer_items WHERE tradeofferid = ?1"
+  )?;
+  let items = stmt.query_map(params![entry.tradeofferid], |row| {
+    Ok(LedgerItem {
+      side: match row.get::<_, String>(0)?.as_str() {
+        "Me" => OfferSide::Me,
+        _ => OfferSide::Them,
+      },
+      appid: row.get(1)?,
+      contextid: row.get(2)?,
+      assetid: row.get(3)?,
+      amount: row.get(4)?,
+      market_hash_name: row.get(5)?,
+      value: row.get::<_, Option<i64>>(6)?.map(|v| v as u64),
+      new_contextid: row.get(7)?,
+      new_assetid: row.get(8)?,
+    })
+  })?;
+  entry.items = items.collect::<Result<Vec<LedgerItem>>>()?;
+
+  let mut stmt = conn.prepare("SELECT from_state, to_state, time, legal FROM state_transitions WHERE tradeofferid = ?1 ORDER BY time, rowid")?;
+  let transitions = stmt.query_map(params![entry.tradeofferid], |row| {
+    Ok(StateChange {
+      from: row.get::<_, Option<i32>>(0)?.map(TradeOfferState::from_i32),
+      to: TradeOfferState::from_i32(row.get(1)?),
+      time: row.get::<_, i64>(2)? as u64,
+      legal: row.get(3)?,
+    })
+  })?;
+  entry.transitions = transitions.collect::<Result<Vec<StateChange>>>()?;
+
+  Ok(entry)
+}
+
+fn evaluation_lines(evaluation: Option<&Evaluation>) -> HashMap<String, (Option<String>, Option<u64>)> {
+  match evaluation {
+    Some(e) => e.lines.iter().map(|l| (l.assetid.to_owned(), (l.market_hash_name.to_owned(), l.value))).collect(),
+    None => HashMap::new(),
+  }
+}
+
+fn inventory_names<'a>(inventories: &[&'a Inventory]) -> HashMap<(i64, &'a str, &'a str), &'a str> {
+  let mut names = HashMap::new();
+
+  for inventory in inventories {
+    let descriptions = inventory.descriptions.iter()
+      .map(|d| ((d.classid.as_str(), d.instanceid.as_str()), d.market_hash_name.as_str()))
+      .collect::<HashMap<(&str, &str), &str>>();
+
+    for asset in &inventory.assets {
+      if let Some(name) = descriptions.get(&(asset.classid.as_str(), asset.instanceid.as_str())) {
+        names.insert((asset.appid, asset.contextid.as_str(), asset.assetid.as_str()), *name);
+      }
+    }
+  }
+
+  names
+}
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::steam::Trade::OfferAsset;
+  use crate::steam::trade_history::{TradeStatus, TradedAsset};
+  use crate::test_support::{description, inventory, offer, offer_item};
+
+  fn success(tradeofferid: &str) -> TradeOfferSuccess {
+    TradeOfferSuccess { tradeofferid: tradeofferid.to_string(), need_mobile_confirmation: None, needs_email_confirmation: None, email_domain: None }
+  }
+
+  #[test]
+  fn record_sent_names_both_sides() {
+    let ours = inventory(vec![("10", description("1", "AK-47 | Redline (Field-Tested)", &[]))]);
+    let theirs = inventory(vec![("20", description("2", "Sticker | Crown (Foil)", &[]))]);
+
+    let mut trade_offer = TradeOffer::new_with_steam_id(SteamId::from_account_id(22202), None);
+    trade_offer.add_self_item(OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), "10".to_string()));
+    trade_offer.add_partner_item(OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), "20".to_string()));
+    trade_offer.add_partner_item(OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), "30".to_string()));
+
+    let ledger = Ledger::open_in_memory().unwrap();
+    assert!(!ledger.contains(&"1".to_string()).unwrap());
+    ledger.record_sent(&trade_offer, &success("1"), &[&ours, &theirs], None).unwrap();
+    assert!(ledger.contains(&"1".to_string()).unwrap());
+
+    let entry = ledger.get(&"1".to_string()).unwrap().unwrap();
+    let names = entry.items.iter().map(|i| (i.side, i.market_hash_name.as_deref())).collect::<Vec<_>>();
+    assert_eq!(names, vec![
+      (OfferSide::Me, Some("AK-47 | Redline (Field-Tested)")),
+      (OfferSide::Them, Some("Sticker | Crown (Foil)")),
+      (OfferSide::Them, None),
+    ]);
+
+    assert_eq!(ledger.by_item("Sticker | Crown (Foil)").unwrap().len(), 1);
+    assert_eq!(entry.tradeofferid_countered, None);
+    assert_eq!(ledger.by_partner(&SteamId::from_account_id(22202)).unwrap().len(), 1);
+  }
+
+  #[test]
+  fn counter_links_survive_a_restart() {
+    let mut original = offer(Vec::new(), Vec::new());
+    original.tradeofferid = "100".to_string();
+
+    let mut counter = TradeOffer::counter(&original);
+    counter.add_self_item(OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), "10".to_string()));
+
+    let ledger = Ledger::open_in_memory().unwrap();
+    ledger.record_sent(&counter, &success("101"), &[], None).unwrap();
+    assert_eq!(ledger.get(&"101".to_string()).unwrap().unwrap().tradeofferid_countered, Some("100".to_string()));
+
+    let mut tracker = TradeOfferStateTracker::new();
+    ledger.restore_counters(&mut tracker).unwrap();
+    assert_eq!(tracker.countered_by(&"100".to_string()), Some(&"101".to_string()));
+    assert_eq!(tracker.counter_of(&"101".to_string()), Some(&"100".to_string()));
+  }
+
+  fn traded(assetid: &str, new_assetid: &str) -> TradedAsset {
+    TradedAsset {
+      appid: 730,
+      contextid: "2".to_string(),
+      assetid: assetid.to_string(),
+      amount: "1".to_string(),
+      classid: "1".to_string(),
+      instanceid: "0".to_string(),
+      new_contextid: Some("2".to_string()),
+      new_assetid: Some(new_assetid.to_string()),
+      description: None,
+    }
+  }
+
+  fn receipt(assets_given: Vec<TradedAsset>, assets_received: Vec<TradedAsset>) -> TradeReceipt {
+    TradeReceipt { tradeid: "900".to_string(), partner: SteamId::from_account_id(22202), time_init: 1000, status: TradeStatus::Complete, assets_given, assets_received }
+  }
+
+  fn entry_at(tradeofferid: &str, recorded_at: u64) -> LedgerEntry {
+    LedgerEntry {
+      tradeofferid: tradeofferid.to_string(),
+      direction: LedgerDirection::Sent,
+      action: LedgerAction::Sent,
+      partner: SteamId::from_account_id(22202),
+      message: String::new(),
+      state: None,
+      tradeid: None,
+      our_value: None,
+      their_value: None,
+      recorded_at,
+      tradeofferid_countered: None,
+      items: Vec::new(),
+      transitions: Vec::new(),
+    }
+  }
+
+  fn user_version(ledger: &Ledger) -> i64 {
+    ledger.conn.lock().unwrap().query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0)).unwrap()
+  }
+
+  #[test]
+  fn new_ledgers_are_at_the_latest_version() {
+    let ledger = Ledger::open_in_memory().unwrap();
+    assert_eq!(user_version(&ledger), MIGRATIONS.len() as i64);
+
+    // Running them again is a no-op
+    migrate(&mut ledger.conn.lock().unwrap(), &MIGRATIONS).unwrap();
+    assert_eq!(user_version(&ledger), MIGRATIONS.len() as i64);
+  }
+
+  #[test]
+  fn upgrades_a_v1_ledger() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    migrate(&mut conn, &MIGRATIONS[..1]).unwrap();
+    conn.execute(
+      "INSERT INTO offers (tradeofferid, direction, action, partner, message, state, recorded_at) VALUES ('1', 'Received', 'Accepted', ?1, 'hi', 3, 50)",
+      params![SteamId::from_account_id(22202).to_string()],
+    ).unwrap();
+    conn.execute("INSERT INTO offer_items (tradeofferid, side, appid, contextid, assetid, amount) VALUES ('1', 'Them', 730, '2', '20', '1')", []).unwrap();
+
+    let ledger = Ledger::init(conn).unwrap();
+    assert_eq!(user_version(&ledger), MIGRATIONS.len() as i64);
+
+    let entry = ledger.get(&"1".to_string()).unwrap().unwrap();
+    assert_eq!((entry.action, entry.state, entry.tradeofferid_countered), (LedgerAction::Accepted, Some(TradeOfferState::Accepted), None));
+    assert_eq!(entry.items.len(), 1);
+
+    // The send_keys table from v2 is there
+    ledger.begin_send_key(&SendKeyRecord { key: "k".to_string(), partner: SteamId::from_account_id(22202), assets: Vec::new(), created_at: 1, success: None }).unwrap();
+    assert!(ledger.get_send_key("k").unwrap().is_some());
+  }
+
+  #[test]
+  fn record_received_keeps_direction_and_state() {
+    let mut offer = offer(
+      vec![offer_item("10", Some(description("1", "AK-47 | Redline (Field-Tested)", &[])))],
+      vec![offer_item("20", None)],
+    );
+    offer.message = "hi".to_string();
+
+    let ledger = Ledger::open_in_memory().unwrap();
+    ledger.record_received(&offer, LedgerAction::Declined, None).unwrap();
+
+    let entry = ledger.get(&"1".to_string()).unwrap().unwrap();
+    assert_eq!((entry.direction, entry.action, entry.state), (LedgerDirection::Received, LedgerAction::Declined, Some(TradeOfferState::Active)));
+    assert_eq!(entry.message, "hi");
+    assert_eq!(entry.items.iter().map(|i| (i.side, i.assetid.as_str(), i.market_hash_name.as_deref())).collect::<Vec<_>>(), vec![
+      (OfferSide::Me, "10", Some("AK-47 | Redline (Field-Tested)")),
+      (OfferSide::Them, "20", None),
+    ]);
+
+    offer.tradeofferid = "2".to_string();
+    offer.is_our_offer = true;
+    ledger.record_received(&offer, LedgerAction::Canceled, None).unwrap();
+    assert_eq!(ledger.get(&"2".to_string()).unwrap().unwrap().direction, LedgerDirection::Sent);
+  }
+
+  #[test]
+  fn record_state_adds_transitions() {
+    let ledger = Ledger::open_in_memory().unwrap();
+    ledger.record_received(&offer(Vec::new(), Vec::new()), LedgerAction::Accepted, None).unwrap();
+
+    let changes = [
+      StateChange { from: Some(TradeOfferState::Active), to: TradeOfferState::InEscrow, time: 10, legal: true },
+      StateChange { from: Some(TradeOfferState::InEscrow), to: TradeOfferState::Accepted, time: 20, legal: true },
+    ];
+    for change in &changes {
+      ledger.record_state(&"1".to_string(), change).unwrap();
+    }
+
+    let entry = ledger.get(&"1".to_string()).unwrap().unwrap();
+    assert_eq!(entry.state, Some(TradeOfferState::Accepted));
+    assert_eq!(entry.transitions, changes.to_vec());
+  }
+
+  #[test]
+  fn record_receipt_maps_new_asset_ids() {
+    let ledger = Ledger::open_in_memory().unwrap();
+    ledger.record_received(&offer(vec![offer_item("10", None)], vec![offer_item("20", None)]), LedgerAction::Accepted, None).unwrap();
+    ledger.record_receipt(&"1".to_string(), &receipt(vec![traded("10", "110")], vec![traded("20", "120")])).unwrap();
+
+    let entry = ledger.get(&"1".to_string()).unwrap().unwrap();
+    assert_eq!(entry.tradeid, Some("900".to_string()));
+    assert_eq!(entry.items.iter().map(|i| (i.side, i.new_assetid.as_deref())).collect::<Vec<_>>(), vec![(OfferSide::Me, Some("110")), (OfferSide::Them, Some("120"))]);
+
+    // Found by the old id and the id it became
+    assert_eq!(ledger.by_item("20").unwrap().len(), 1);
+    assert_eq!(ledger.by_item("120").unwrap().len(), 1);
+    assert!(ledger.by_item("130").unwrap().is_empty());
+  }
+
+  #[test]
+  fn date_range_includes_from_and_excludes_to() {
+    let ledger = Ledger::open_in_memory().unwrap();
+    for (tradeofferid, recorded_at) in [("1", 99), ("2", 100), ("3", 150), ("4", 200)] {
+      ledger.insert(&entry_at(tradeofferid, recorded_at)).unwrap();
+    }
+
+    let ids = |from, to| ledger.by_date_range(from, to).unwrap().into_iter().map(|e| e.tradeofferid).collect::<Vec<String>>();
+    assert_eq!(ids(100, 200), vec!["2", "3"]);
+    assert_eq!(ids(0, 201), vec!["1", "2", "3", "4"]);
+    assert!(ids(100, 100).is_empty());
+  }
+
+  #[test]
+  fn recording_again_keeps_what_was_learned() {
+    let mut original = offer(Vec::new(), Vec::new());
+    original.tradeofferid = "100".to_string();
+
+    let mut counter = TradeOffer::counter(&original);
+    counter.add_self_item(OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), "10".to_string()));
+
+    let ledger = Ledger::open_in_memory().unwrap();
+    ledger.record_sent(&counter, &success("101"), &[], None).unwrap();
+    ledger.record_state(&"101".to_string(), &StateChange { from: None, to: TradeOfferState::Accepted, time: 10, legal: true }).unwrap();
+    ledger.record_receipt(&"101".to_string(), &receipt(vec![traded("10", "110")], Vec::new())).unwrap();
+
+    // e.g. send_idempotent_and_record retried after a ledger failure
+    counter.tradeofferid_countered = None;
+    ledger.record_sent(&counter, &success("101"), &[], None).unwrap();
+
+    let entry = ledger.get(&"101".to_string()).unwrap().unwrap();
+    assert_eq!(entry.state, Some(TradeOfferState::Accepted));
+    assert_eq!(entry.tradeid, Some("900".to_string()));
+    assert_eq!(entry.tradeofferid_countered, Some("100".to_string()));
+    assert_eq!(entry.items[0].new_assetid, Some("110".to_string()));
+    assert_eq!(entry.transitions.len(), 1);
+  }
+
+  #[test]
+  fn send_keys_round_trip() {
+    let ledger = Ledger::open_in_memory().unwrap();
+    assert_eq!(ledger.get_send_key("move-1:0").unwrap(), None);
+
+    let mut record = SendKeyRecord {
+      key: "move-1:0".to_string(),
+      partner: SteamId::from_account_id(22202),
+      assets: vec!["me:730:2:10".to_string(), "them:730:2:20".to_string()],
+      created_at: 1700000000,
+      success: None,
+    };
+    ledger.begin_send_key(&record).unwrap();
+    assert_eq!(ledger.get_send_key("move-1:0").unwrap(), Some(record.clone()));
+
+    // A key can only be begun once
+    assert!(ledger.begin_send_key(&record).is_err());
+
+    let mut sent = success("55");
+    sent.need_mobile_confirmation = Some(true);
+    ledger.complete_send_key("move-1:0", &sent).unwrap();
+
+    record.success = Some(sent);
+    assert_eq!(ledger.get_send_key("move-1:0").unwrap(), Some(record));
+  }
+}
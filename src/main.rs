@@ -1,6 +1,13 @@
 use dotenv;
 
 mod steam;
+mod rules;
+mod pricing;
+mod evaluation;
+mod portfolio;
+mod ledger;
+#[cfg(test)]
+mod test_support;
 
 #[tokio::main]
 async fn main() {
@@ -18,10 +25,18 @@ async fn test_trade() {
         Err(e) => panic!("{:?}", e)
     };
 
-    let mut trade_offer = steam::Trade::TradeOffer::new("https://steamcommunity.com/tradeoffer/new/?partner=87048484&token=gn-X8Nub".to_string());
+    let ledger = match ledger::Ledger::open(&dotenv::var("LEDGER_PATH").unwrap_or("ledger.sqlite".to_string())) {
+        Ok(ledger) => ledger,
+        Err(e) => panic!("{:?}", e)
+    };
+
+    let mut trade_offer = match steam::Trade::TradeOffer::new("https://steamcommunity.com/tradeoffer/new/?partner=87048484&token=gn-X8Nub".to_string()) {
+        Ok(trade_offer) => trade_offer,
+        Err(e) => panic!("{:?}", e)
+    };
     trade_offer.set_trade_message("Hello World!".to_string());
 
-    let partner_inventory = steam::Inventory::Inventory::new(trade_offer.partner.to_string(),"730".to_string(), "2".to_string()).await.unwrap();
+    let partner_inventory = steam::Inventory::Inventory::new(trade_offer.partner,"730".to_string(), "2".to_string()).await.unwrap();
     let self_inventory = steam::Inventory::Inventory::new(account.steam_id, "753".to_string(), "6".to_string()).await.unwrap();
 
     let partner_items = match partner_inventory.search_item_name("Shadow Daggers".to_string()) {
@@ -42,7 +57,7 @@ async fn test_trade() {
 
     println!("trade -> {:?}", trade_offer);
 
-    match trade_offer.send(&account.cookie).await {
+    match ledger::send_and_record(&ledger, &mut trade_offer, &account.cookie, &[&self_inventory, &partner_inventory], None).await {
         Ok(trade) => println!("{:?}", trade),
         Err(e) => println!("{:?}", e)
     }
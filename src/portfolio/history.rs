@@ -0,0 +1,449 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use crate::pricing::PriceSource;
+use crate::steam::steam_id::SteamId;
+use crate::steam::trade_history::{TradeReceipt, TradeStatus, TradedAsset};
+use crate::ledger::migrate;
+use super::PortfolioReport;
+
+// Each entry moves the schema one version forward, never edit an applied one
+const MIGRATIONS: [&str; 1] = [
+  "CREATE TABLE snapshots (
+    id INTEGER PRIMARY KEY,
+    steam_id TEXT NOT NULL,
+    taken_at INTEGER NOT NULL,
+    total INTEGER NOT NULL
+  );
+  CREATE INDEX snapshots_steam_id ON snapshots (steam_id, taken_at);
+  CREATE TABLE snapshot_items (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots (id),
+    appid INTEGER NOT NULL,
+    assetid TEXT NOT NULL,
+    market_hash_name TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    price INTEGER
+  );
+  CREATE INDEX snapshot_items_snapshot ON snapshot_items (snapshot_id);
+  CREATE TABLE acquisitions (
+    steam_id TEXT NOT NULL,
+    appid INTEGER NOT NULL,
+    assetid TEXT NOT NULL,
+    market_hash_name TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    cost INTEGER NOT NULL,
+    acquired_at INTEGER NOT NULL,
+    PRIMARY KEY (steam_id, appid, assetid)
+  );
+  CREATE TABLE disposals (
+    steam_id TEXT NOT NULL,
+    appid INTEGER NOT NULL,
+    assetid TEXT NOT NULL,
+    market_hash_name TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    proceeds INTEGER NOT NULL,
+    disposed_at INTEGER NOT NULL,
+    PRIMARY KEY (steam_id, appid, assetid)
+  );",
+];
+
+const INSERT_ACQUISITION: &str = "INSERT OR REPLACE INTO acquisitions (steam_id, appid, assetid, market_hash_name, amount, cost, acquired_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+const INSERT_DISPOSAL: &str = "INSERT OR REPLACE INTO disposals (steam_id, appid, assetid, market_hash_name, amount, proceeds, disposed_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+
+pub struct PortfolioHistory {
+  conn: Connection,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ValuePoint {
+  pub taken_at: u64,
+  pub total: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ItemGain {
+  pub appid: i64,
+  pub assetid: String,
+  pub market_hash_name: String,
+  pub cost: u64,
+  pub acquired_at: u64,
+  pub current_value: Option<u64>,
+  pub gain: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProfitAndLoss {
+  pub realised: i64,
+  pub unrealised: i64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CostEntry {
+  pub steam_id: SteamId,
+  pub appid: i64,
+  pub assetid: String,
+  pub market_hash_name: String,
+  pub amount: u64,
+  pub cents: u64,
+  pub time: u64,
+}
+
+// What a completed trade did to our cost basis: items received and items given away
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeCostBasis {
+  pub acquisitions: Vec<CostEntry>,
+  pub disposals: Vec<CostEntry>,
+}
+
+impl PortfolioHistory {
+  pub fn open(path: &str) -> Result<PortfolioHistory> {
+    PortfolioHistory::init(Connection::open(path)?)
+  }
+
+  pub fn open_in_memory() -> Result<PortfolioHistory> {
+    PortfolioHistory::init(Connection::open_in_memory()?)
+  }
+
+  fn init(mut conn: Connection) -> Result<PortfolioHistory> {
+    migrate(&mut conn, &MIGRATIONS)?;
+    Ok(PortfolioHistory { conn })
+  }
+
+  pub fn record_snapshot(&mut self, report: &PortfolioReport, taken_at: u64) -> Result<Vec<i64>> {
+    let tx = self.conn.transaction()?;
+    let mut ids: Vec<i64> = Vec::new();
+
+    for account in &report.accounts {
+      tx.execute(
+        "INSERT INTO snapshots (steam_id, taken_at, total) VALUES (?1, ?2, ?3)",
+        params![account.steam_id.to_string(), taken_at as i64, account.total as i64],
+      )?;
+      let snapshot_id = tx.last_insert_rowid();
+
+      let items = report.items.iter().chain(report.unpriced.iter()).filter(|i| i.steam_id == account.steam_id);
+      for item in items {
+        tx.execute(
+          "INSERT INTO snapshot_items (snapshot_id, appid, assetid, market_hash_name, amount, price) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+          params![snapshot_id, item.appid, item.assetid, item.market_hash_name, item.amount as i64, item.price.map(|p| p as i64)],
+        )?;
+      }
+
+      ids.push(snapshot_id);
+    }
+
+    tx.commit()?;
+
+    Ok(ids)
+  }
+
+  pub fn record_acquisition(&self, entry: &CostEntry) -> Result<()> {
+    insert_cost_entry(&self.conn, INSERT_ACQUISITION, entry)
+  }
+
+  pub fn record_disposal(&self, entry: &CostEntry) -> Result<()> {
+    insert_cost_entry(&self.conn, INSERT_DISPOSAL, entry)
+  }
+
+  pub fn record_cost_basis(&mut self, basis: &TradeCostBasis) -> Result<()> {
+    let tx = self.conn.transaction()?;
+
+    for entry in &basis.acquisitions {
+      insert_cost_entry(&tx, INSERT_ACQUISITION, entry)?;
+    }
+
+    for entry in &basis.disposals {
+      insert_cost_entry(&tx, INSERT_DISPOSAL, entry)?;
+    }
+
+    tx.commit()
+  }
+
+  pub fn value_series(&self, steam_id: &SteamId) -> Result<Vec<ValuePoint>> {
+    let mut stmt = self.conn.prepare("SELECT taken_at, total FROM snapshots WHERE steam_id = ?1 ORDER BY taken_at")?;
+
+    let points = stmt.query_map(params![steam_id.to_string()], |row| {
+      Ok(ValuePoint { taken_at: row.get::<_, i64>(0)? as u64, total: row.get::<_, i64>(1)? as u64 })
+    })?;
+
+    points.collect()
+  }
+
+  // Gain of every currently held item that has a known acquisition cost, valued at the latest snapshot
+  pub fn item_gains(&self, steam_id: &SteamId) -> Result<Vec<ItemGain>> {
+    let latest = match self.latest_snapshot(steam_id)? {
+      Some(id) => id,
+      None => return Ok(Vec::new()),
+    };
+
+    let mut stmt = self.conn.prepare(
+      "SELECT a.appid, a.assetid, a.market_hash_name, a.cost, a.acquired_at, i.price, i.amount
+       FROM snapshot_items i
+       JOIN acquisitions a ON a.appid = i.appid AND a.assetid = i.assetid
+       WHERE i.snapshot_id = ?1 AND a.steam_id = ?2
+       ORDER BY a.acquired_at"
+    )?;
+
+    let gains = stmt.query_map(params![latest, steam_id.to_string()], |row| {
+      let cost = row.get::<_, i64>(3)?;
+      let price = row.get::<_, Option<i64>>(5)?;
+      let amount = row.get::<_, i64>(6)?;
+      let current_value = price.map(|p| p * amount);
+
+      Ok(ItemGain {
+        appid: row.get(0)?,
+        assetid: row.get(1)?,
+        market_hash_name: row.get(2)?,
+        cost: cost as u64,
+        acquired_at: row.get::<_, i64>(4)? as u64,
+        current_value: current_value.map(|v| v as u64),
+        gain: current_value.map(|v| v - cost),
+      })
+    })?;
+
+    gains.collect()
+  }
+
+  // Realised covers everything we acquired and later gave away, unrealised what we still hold
+  pub fn profit_and_loss(&self, steam_id: &SteamId) -> Result<ProfitAndLoss> {
+    let realised = self.conn.query_row(
+      "SELECT COALESCE(SUM(d.proceeds - a.cost), 0)
+       FROM disposals d
+       JOIN acquisitions a ON a.steam_id = d.steam_id AND a.appid = d.appid AND a.assetid = d.assetid
+       WHERE d.steam_id = ?1",
+      params![steam_id.to_string()],
+      |row| row.get::<_, i64>(0),
+    )?;
+
+    let unrealised = self.item_gains(steam_id)?.iter().filter_map(|g| g.gain).sum();
+
+    Ok(ProfitAndLoss { realised, unrealised })
+  }
+
+  fn latest_snapshot(&self, steam_id: &SteamId) -> Result<Option<i64>> {
+    self.conn.query_row(
+      "SELECT id FROM snapshots WHERE steam_id = ?1 ORDER BY taken_at DESC, id DESC LIMIT 1",
+      params![steam_id.to_string()],
+      |row| row.get(0),
+    ).optional()
+  }
+}
+
+// Splits the market value of what we gave across what we received, weighted by each item's own price.
+// Given items are booked as disposals with proceeds split the same way across the received value.
+pub async fn trade_cost_basis(steam_id: SteamId, receipt: &TradeReceipt, prices: &dyn PriceSource) -> Option<TradeCostBasis> {
+  if receipt.status != TradeStatus::Complete {
+    return None;
+  }
+
+  let given = price_assets(&receipt.assets_given, prices).await;
+  let received = price_assets(&receipt.assets_received, prices).await;
+
+  let given_total: u64 = given.iter().map(|(_, v)| v).sum();
+  let received_total: u64 = received.iter().map(|(_, v)| v).sum();
+
+  let acquisitions = received.iter().filter_map(|(asset, value)| {
+    let cents = share(*value, received_total, given_total, received.len() as u64);
+
+    Some(CostEntry {
+      steam_id,
+      appid: asset.appid,
+      assetid: asset.new_assetid.to_owned()?,
+      market_hash_name: market_hash_name(asset),
+      amount: asset.amount.parse::<u64>().unwrap_or(1),
+      cents,
+      time: receipt.time_init,
+    })
+  }).collect();
+
+  let disposals = given.iter().map(|(asset, value)| {
+    let cents = share(*value, given_total, received_total, given.len() as u64);
+
+    CostEntry {
+      steam_id,
+      appid: asset.appid,
+      assetid: asset.assetid.to_owned(),
+      market_hash_name: market_hash_name(asset),
+      amount: asset.amount.parse::<u64>().unwrap_or(1),
+      cents,
+      time: receipt.time_init,
+    }
+  }).collect();
+
+  Some(TradeCostBasis { acquisitions, disposals })
+}
+
+// Acquisitions and disposals share a layout, only the table and column names differ
+fn insert_cost_entry(conn: &Connection, sql: &str, entry: &CostEntry) -> Result<()> {
+  conn.execute(sql, params![entry.steam_id.to_string(), entry.appid, entry.assetid, entry.market_hash_name, entry.amount as i64, entry.cents as i64, entry.time as i64])?;
+  Ok(())
+}
+
+async fn price_assets<'a>(assets: &'a [TradedAsset], prices: &dyn PriceSource) -> Vec<(&'a TradedAsset, u64)> {
+  let mut priced: Vec<(&TradedAsset, u64)> = Vec::new();
+
+  for asset in assets {
+    let amount = asset.amount.parse::<u64>().unwrap_or(1);
+    let price = match &asset.description {
+      Some(d) => prices.price(asset.appid, &d.market_hash_name).await.unwrap_or(0),
+      None => 0,
+    };
+
+    priced.push((asset, price * amount));
+  }
+
+  priced
+}
+
+// Steam sometimes leaves out a description, the item still counts towards the trade
+fn market_hash_name(asset: &TradedAsset) -> String {
+  asset.description.as_ref().map(|d| d.market_hash_name.to_owned()).unwrap_or_default()
+}
+
+// Items without a price share the pool evenly when nothing on the side could be priced
+fn share(value: u64, side_total: u64, pool: u64, count: u64) -> u64 {
+  match side_total {
+    0 => pool / count.max(1),
+    total => (pool as u128 * value as u128 / total as u128) as u64,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::pricing::StaticPriceSource;
+  use crate::portfolio::value_inventories;
+  use crate::steam::Inventory::AssetDescription;
+  use crate::test_support::{description, inventory};
+
+  fn entry(steam_id: SteamId, assetid: &str, cents: u64, time: u64) -> CostEntry {
+    CostEntry { steam_id, appid: 730, assetid: assetid.to_string(), market_hash_name: format!("Item {}", assetid), amount: 1, cents, time }
+  }
+
+  #[tokio::test]
+  async fn snapshots_store_every_item_regardless_of_top_n() {
+    let mut prices = StaticPriceSource::new();
+    prices.insert(730, "a".to_string(), 300);
+    prices.insert(730, "b".to_string(), 200);
+    prices.insert(730, "c".to_string(), 100);
+
+    let inventory = inventory(vec![("1", description("1", "a", &[])), ("2", description("2", "b", &[])), ("3", description("3", "c", &[]))]);
+    let steam_id = SteamId::from_account_id(1);
+    let report = value_inventories(&[(steam_id, &inventory)], &prices, 1).await;
+    assert_eq!(report.top_items.len(), 1);
+
+    let mut history = PortfolioHistory::open_in_memory().unwrap();
+    history.record_snapshot(&report, 100).unwrap();
+    for (assetid, cost, time) in [("1", 250, 50), ("2", 250, 51), ("3", 50, 52)] {
+      history.record_acquisition(&entry(steam_id, assetid, cost, time)).unwrap();
+    }
+
+    let gains = history.item_gains(&steam_id).unwrap();
+    assert_eq!(gains.iter().map(|g| g.gain).collect::<Vec<_>>(), vec![Some(50), Some(-50), Some(50)]);
+    assert_eq!(history.value_series(&steam_id).unwrap(), vec![ValuePoint { taken_at: 100, total: 600 }]);
+  }
+
+  #[test]
+  fn profit_and_loss_is_per_account() {
+    let ours = SteamId::from_account_id(1);
+    let other = SteamId::from_account_id(2);
+
+    let mut history = PortfolioHistory::open_in_memory().unwrap();
+    history.record_cost_basis(&TradeCostBasis {
+      acquisitions: vec![entry(ours, "1", 100, 10), entry(other, "2", 100, 10)],
+      disposals: vec![entry(ours, "1", 150, 20), entry(other, "2", 40, 20)],
+    }).unwrap();
+
+    assert_eq!(history.profit_and_loss(&ours).unwrap(), ProfitAndLoss { realised: 50, unrealised: 0 });
+    assert_eq!(history.profit_and_loss(&other).unwrap(), ProfitAndLoss { realised: -60, unrealised: 0 });
+    assert_eq!(history.profit_and_loss(&SteamId::from_account_id(3)).unwrap(), ProfitAndLoss { realised: 0, unrealised: 0 });
+  }
+
+  fn traded(assetid: &str, new_assetid: Option<&str>, description: Option<AssetDescription>) -> TradedAsset {
+    TradedAsset {
+      appid: 730,
+      contextid: "2".to_string(),
+      assetid: assetid.to_string(),
+      amount: "1".to_string(),
+      classid: "1".to_string(),
+      instanceid: "0".to_string(),
+      new_contextid: new_assetid.map(|_| "2".to_string()),
+      new_assetid: new_assetid.map(|a| a.to_string()),
+      description,
+    }
+  }
+
+  fn receipt(status: TradeStatus, assets_given: Vec<TradedAsset>, assets_received: Vec<TradedAsset>) -> TradeReceipt {
+    TradeReceipt { tradeid: "1".to_string(), partner: SteamId::from_account_id(22202), time_init: 1000, status, assets_given, assets_received }
+  }
+
+  #[test]
+  fn shares_by_value() {
+    assert_eq!(share(150, 200, 400, 2), 300);
+    assert_eq!(share(50, 200, 400, 2), 100);
+    assert_eq!(share(0, 200, 400, 2), 0);
+
+    // Nothing on the side was priced
+    assert_eq!(share(0, 0, 400, 3), 133);
+    assert_eq!(share(0, 0, 400, 0), 400);
+
+    // No overflow on huge pools
+    assert_eq!(share(u64::MAX / 2, u64::MAX, u64::MAX, 2), u64::MAX / 2);
+  }
+
+  #[tokio::test]
+  async fn trade_cost_basis_splits_each_side_by_value() {
+    let mut prices = StaticPriceSource::new();
+    prices.insert(730, "a".to_string(), 100);
+    prices.insert(730, "b".to_string(), 300);
+    prices.insert(730, "c".to_string(), 150);
+    prices.insert(730, "d".to_string(), 50);
+
+    let steam_id = SteamId::from_account_id(1);
+    let receipt = receipt(
+      TradeStatus::Complete,
+      vec![traded("1", None, Some(description("1", "a", &[]))), traded("2", None, Some(description("2", "b", &[])))],
+      vec![traded("3", Some("30"), Some(description("3", "c", &[]))), traded("4", Some("40"), Some(description("4", "d", &[])))],
+    );
+
+    let basis = trade_cost_basis(steam_id, &receipt, &prices).await.unwrap();
+
+    // 400 given for 200 received
+    let cost = |assetid: &str, name: &str, cents: u64| CostEntry { steam_id, appid: 730, assetid: assetid.to_string(), market_hash_name: name.to_string(), amount: 1, cents, time: 1000 };
+    assert_eq!(basis.acquisitions, vec![cost("30", "c", 300), cost("40", "d", 100)]);
+    assert_eq!(basis.disposals, vec![cost("1", "a", 50), cost("2", "b", 150)]);
+  }
+
+  #[tokio::test]
+  async fn trade_cost_basis_keeps_items_without_descriptions() {
+    let mut prices = StaticPriceSource::new();
+    prices.insert(730, "a".to_string(), 100);
+
+    let steam_id = SteamId::from_account_id(1);
+    let receipt = receipt(TradeStatus::Complete, vec![traded("1", None, Some(description("1", "a", &[])))], vec![traded("3", Some("30"), None), traded("4", Some("40"), None)]);
+
+    let basis = trade_cost_basis(steam_id, &receipt, &prices).await.unwrap();
+
+    // Neither received item could be priced, so they split what we gave evenly
+    assert_eq!(basis.acquisitions.iter().map(|e| (e.assetid.as_str(), e.market_hash_name.as_str(), e.cents)).collect::<Vec<_>>(), vec![("30", "", 50), ("40", "", 50)]);
+    assert_eq!(basis.disposals.iter().map(|e| e.cents).collect::<Vec<u64>>(), vec![0]);
+  }
+
+  #[tokio::test]
+  async fn trade_cost_basis_needs_a_complete_trade() {
+    let prices = StaticPriceSource::new();
+    let receipt = receipt(TradeStatus::InEscrow, vec![traded("1", None, None)], vec![traded("3", Some("30"), None)]);
+
+    assert_eq!(trade_cost_basis(SteamId::from_account_id(1), &receipt, &prices).await, None);
+  }
+
+  #[test]
+  fn migrations_run_once() {
+    let mut history = PortfolioHistory::open_in_memory().unwrap();
+    history.record_acquisition(&entry(SteamId::from_account_id(1), "1", 100, 10)).unwrap();
+
+    migrate(&mut history.conn, &MIGRATIONS).unwrap();
+
+    let version = history.conn.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0)).unwrap();
+    assert_eq!(version, MIGRATIONS.len() as i64);
+    assert_eq!(history.conn.query_row("SELECT COUNT(*) FROM acquisitions", [], |row| row.get::<_, i64>(0)).unwrap(), 1);
+  }
+}
@@ -0,0 +1,222 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::pricing::PriceSource;
+use crate::steam::Inventory::{AssetDescription, Inventory};
+use crate::steam::steam_id::SteamId;
+
+pub mod history;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PortfolioReport {
+  pub total: u64,
+  pub accounts: Vec<AccountValuation>,
+  pub by_type: BTreeMap<String, u64>,
+  pub by_rarity: BTreeMap<String, u64>,
+  pub by_exterior: BTreeMap<String, u64>,
+  // Every priced item, most valuable first
+  pub items: Vec<ValuedItem>,
+  pub top_items: Vec<ValuedItem>,
+  pub unpriced: Vec<ValuedItem>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AccountValuation {
+  pub steam_id: SteamId,
+  pub total: u64,
+  pub item_count: u64,
+  pub unpriced_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ValuedItem {
+  pub steam_id: SteamId,
+  pub appid: i64,
+  pub assetid: String,
+  pub market_hash_name: String,
+  pub amount: u64,
+  pub price: Option<u64>,
+  pub value: Option<u64>,
+}
+
+// Values are in cents of the price source's currency
+pub async fn value_inventories(inventories: &[(SteamId, &Inventory)], prices: &dyn PriceSource, top_n: usize) -> PortfolioReport {
+  let mut report = PortfolioReport {
+    total: 0,
+    accounts: Vec::new(),
+    by_type: BTreeMap::new(),
+    by_rarity: BTreeMap::new(),
+    by_exterior: BTreeMap::new(),
+    items: Vec::new(),
+    top_items: Vec::new(),
+    unpriced: Vec::new(),
+  };
+
+  for (steam_id, inventory) in inventories {
+    let mut account = AccountValuation { steam_id: *steam_id, total: 0, item_count: 0, unpriced_count: 0 };
+
+    let descriptions = inventory.descriptions.iter()
+      .map(|d| ((d.classid.as_str(), d.instanceid.as_str()), d))
+      .collect::<HashMap<(&str, &str), &AssetDescription>>();
+
+    for asset in &inventory.assets {
+      let amount = asset.amount.parse::<u64>().unwrap_or(1);
+
+      // Without a description there is no name to price, the item still counts as held
+      let description = match descriptions.get(&(asset.classid.as_str(), asset.instanceid.as_str())) {
+        Some(d) => *d,
+        None => {
+          account.item_count += amount;
+          account.unpriced_count += amount;
+          report.unpriced.push(ValuedItem {
+            steam_id: *steam_id,
+            appid: asset.appid,
+            assetid: asset.assetid.to_owned(),
+            market_hash_name: String::new(),
+            amount,
+            price: None,
+            value: None,
+          });
+          continue;
+        }
+      };
+
+      let price = prices.price(asset.appid, &description.market_hash_name).await;
+      let value = price.map(|p| p * amount);
+
+      let item = ValuedItem {
+        steam_id: *steam_id,
+        appid: asset.appid,
+        assetid: asset.assetid.to_owned(),
+        market_hash_name: description.market_hash_name.to_owned(),
+        amount,
+        price,
+        value,
+      };
+
+      account.item_count += amount;
+
+      let value = match value {
+        Some(v) => v,
+        None => {
+          account.unpriced_count += amount;
+          report.unpriced.push(item);
+          continue;
+        }
+      };
+
+      account.total += value;
+
+      *report.by_type.entry(bucket(description.item_type())).or_insert(0) += value;
+      *report.by_rarity.entry(bucket(description.rarity())).or_insert(0) += value;
+      *report.by_exterior.entry(bucket(description.exterior())).or_insert(0) += value;
+
+      report.items.push(item);
+    }
+
+    report.total += account.total;
+    report.accounts.push(account);
+  }
+
+  report.items.sort_by_key(|i| Reverse(i.value));
+  report.top_items = report.items.iter().take(top_n).cloned().collect();
+
+  report
+}
+
+fn bucket<T: std::fmt::Debug>(value: Option<T>) -> String {
+  match value {
+    Some(v) => format!("{:?}", v),
+    None => "Unknown".to_string(),
+  }
+}
+
+impl PortfolioReport {
+  pub fn to_json(&self) -> String {
+    serde_json::to_string_pretty(self).unwrap()
+  }
+
+  pub fn to_table(&self) -> String {
+    let mut table = String::new();
+
+    table.push_str(&format!("{:<40} {:>14}\n", "Total", cents(self.total)));
+
+    table.push_str("\nAccounts\n");
+    for account in &self.accounts {
+      table.push_str(&format!("{:<20} {:>8} items {:>6} unpriced {:>14}\n", account.steam_id, account.item_count, account.unpriced_count, cents(account.total)));
+    }
+
+    for (title, buckets) in [("Type", &self.by_type), ("Rarity", &self.by_rarity), ("Exterior", &self.by_exterior)] {
+      table.push_str(&format!("\nBy {}\n", title));
+      for (name, value) in buckets {
+        table.push_str(&format!("{:<40} {:>14}\n", name, cents(*value)));
+      }
+    }
+
+    table.push_str("\nTop items\n");
+    for item in &self.top_items {
+      table.push_str(&format!("{:<60} {:>4} {:>14}\n", item.market_hash_name, item.amount, cents(item.value.unwrap_or(0))));
+    }
+
+    table.push_str(&format!("\nUnpriced items: {}\n", self.unpriced.len()));
+    for item in &self.unpriced {
+      table.push_str(&format!("{:<60} {:>4} {}\n", item.market_hash_name, item.amount, item.assetid));
+    }
+
+    table
+  }
+}
+
+fn cents(value: u64) -> String {
+  format!("{}.{:02}", value / 100, value % 100)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::pricing::StaticPriceSource;
+  use crate::test_support::{description, inventory};
+
+  fn prices() -> StaticPriceSource {
+    let mut prices = StaticPriceSource::new();
+    prices.insert(730, "AK-47 | Redline (Field-Tested)".to_string(), 1000);
+    prices.insert(730, "Sticker | Crown (Foil)".to_string(), 5000);
+    prices
+  }
+
+  #[tokio::test]
+  async fn values_and_buckets_items() {
+    let inventory = inventory(vec![
+      ("1", description("1", "AK-47 | Redline (Field-Tested)", &[("Rarity", "Classified"), ("Type", "Rifle"), ("Exterior", "Field-Tested")])),
+      ("2", description("1", "AK-47 | Redline (Field-Tested)", &[("Rarity", "Classified"), ("Type", "Rifle"), ("Exterior", "Field-Tested")])),
+      ("3", description("2", "Sticker | Crown (Foil)", &[("Rarity", "Extraordinary"), ("Type", "Sticker")])),
+      ("4", description("3", "Unpriced Case", &[("Type", "Container")])),
+    ]);
+    let steam_id = SteamId::from_account_id(22202);
+
+    let report = value_inventories(&[(steam_id, &inventory)], &prices(), 2).await;
+
+    assert_eq!(report.total, 7000);
+    assert_eq!(report.accounts, vec![AccountValuation { steam_id, total: 7000, item_count: 4, unpriced_count: 1 }]);
+    assert_eq!(report.top_items.iter().map(|i| i.value).collect::<Vec<_>>(), vec![Some(5000), Some(1000)]);
+    assert_eq!(report.items.len(), 3);
+    assert_eq!(report.unpriced.iter().map(|i| i.assetid.as_str()).collect::<Vec<_>>(), vec!["4"]);
+    assert_eq!(report.by_type.get("Rifle"), Some(&2000));
+    assert_eq!(report.by_exterior.get("Unknown"), Some(&5000));
+  }
+
+  #[tokio::test]
+  async fn assets_without_descriptions_are_unpriced() {
+    let mut inventory = inventory(vec![("1", description("1", "AK-47 | Redline (Field-Tested)", &[]))]);
+    inventory.descriptions.clear();
+    let steam_id = SteamId::from_account_id(22202);
+
+    let report = value_inventories(&[(steam_id, &inventory)], &prices(), 10).await;
+
+    assert_eq!(report.total, 0);
+    assert_eq!(report.accounts[0].item_count, 1);
+    assert_eq!(report.accounts[0].unpriced_count, 1);
+    assert_eq!(report.unpriced[0].assetid, "1");
+    assert_eq!(report.unpriced[0].value, None);
+  }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use super::PriceSource;
+
+type PriceEntries = HashMap<(i64, String), (u64, Instant)>;
+
+// Cheap to clone, every clone shares the same entries
+#[derive(Clone, Debug)]
+pub struct PriceCache {
+  ttl: Duration,
+  entries: Arc<RwLock<PriceEntries>>,
+}
+
+pub struct CachedPriceSource<S: PriceSource> {
+  source: S,
+  cache: PriceCache,
+}
+
+impl PriceCache {
+  pub fn new(ttl: Duration) -> PriceCache {
+    PriceCache { ttl, entries: Arc::new(RwLock::new(HashMap::new())) }
+  }
+
+  pub fn get(&self, appid: i64, market_hash_name: &str) -> Option<u64> {
+    let entries = self.entries.read().unwrap();
+
+    match entries.get(&(appid, market_hash_name.to_string())) {
+      Some((price, fetched)) if fetched.elapsed() < self.ttl => Some(*price),
+      _ => None,
+    }
+  }
+
+  pub fn insert(&self, appid: i64, market_hash_name: &str, price: u64) {
+    let mut entries = self.entries.write().unwrap();
+    entries.insert((appid, market_hash_name.to_string()), (price, Instant::now()));
+  }
+
+  pub fn evict_expired(&self) {
+    let mut entries = self.entries.write().unwrap();
+    entries.retain(|_, (_, fetched)| fetched.elapsed() < self.ttl);
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.read().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+impl<S: PriceSource> CachedPriceSource<S> {
+  pub fn new(source: S, cache: PriceCache) -> CachedPriceSource<S> {
+    CachedPriceSource { source, cache }
+  }
+
+  pub fn cache(&self) -> &PriceCache {
+    &self.cache
+  }
+}
+
+#[async_trait]
+impl<S: PriceSource> PriceSource for CachedPriceSource<S> {
+  async fn price(&self, appid: i64, market_hash_name: &str) -> Option<u64> {
+    if let Some(price) = self.cache.get(appid, market_hash_name) {
+      return Some(price);
+    }
+
+    let price = self.source.price(appid, market_hash_name).await?;
+    self.cache.insert(appid, market_hash_name, price);
+
+    Some(price)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use crate::pricing::StaticPriceSource;
+
+  // Counts lookups so tests can tell cache hits from misses
+  struct CountingSource {
+    prices: StaticPriceSource,
+    calls: Arc<AtomicUsize>,
+  }
+
+  #[async_trait]
+  impl PriceSource for CountingSource {
+    async fn price(&self, appid: i64, market_hash_name: &str) -> Option<u64> {
+      self.calls.fetch_add(1, Ordering::SeqCst);
+      self.prices.price(appid, market_hash_name).await
+    }
+  }
+
+  fn counting(ttl: Duration) -> (CachedPriceSource<CountingSource>, Arc<AtomicUsize>) {
+    let mut prices = StaticPriceSource::new();
+    prices.insert(730, "Mann Co. Supply Crate Key".to_string(), 250);
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let source = CountingSource { prices, calls: Arc::clone(&calls) };
+
+    (CachedPriceSource::new(source, PriceCache::new(ttl)), calls)
+  }
+
+  #[test]
+  fn entries_expire_after_ttl() {
+    let cache = PriceCache::new(Duration::from_millis(50));
+    cache.insert(730, "a", 1);
+
+    assert_eq!(cache.get(730, "a"), Some(1));
+    assert_eq!(cache.get(440, "a"), None);
+
+    std::thread::sleep(Duration::from_millis(80));
+    assert_eq!(cache.get(730, "a"), None);
+    assert_eq!(cache.len(), 1);
+
+    cache.evict_expired();
+    assert!(cache.is_empty());
+  }
+
+  #[test]
+  fn clones_share_entries() {
+    let cache = PriceCache::new(Duration::from_secs(60));
+    let clone = cache.clone();
+
+    clone.insert(730, "a", 1);
+    assert_eq!(cache.get(730, "a"), Some(1));
+  }
+
+  #[tokio::test]
+  async fn cached_source_only_hits_the_source_on_miss() {
+    let (source, calls) = counting(Duration::from_secs(60));
+
+    assert_eq!(source.price(730, "Mann Co. Supply Crate Key").await, Some(250));
+    assert_eq!(source.price(730, "Mann Co. Supply Crate Key").await, Some(250));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Misses are not cached so a price that shows up later is picked up
+    assert_eq!(source.price(730, "unknown").await, None);
+    assert_eq!(source.price(730, "unknown").await, None);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(source.cache().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn cached_source_refetches_after_ttl() {
+    let (source, calls) = counting(Duration::from_millis(50));
+
+    source.price(730, "Mann Co. Supply Crate Key").await;
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    source.price(730, "Mann Co. Supply Crate Key").await;
+
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+  }
+}
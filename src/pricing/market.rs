@@ -0,0 +1,145 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use reqwest::{Client, StatusCode};
+use super::PriceSource;
+use super::money::{Currency, FxRateTable, FxRates, Money};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PriceOverview {
+  pub success: bool,
+  pub lowest_price: Option<String>,
+  pub volume: Option<String>,
+  pub median_price: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SteamMarketPriceSource {
+  base_url: String,
+  currency: Currency,
+  fx: Option<FxRateTable>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct PriceOverviewQuery<'a> {
+  appid: i64,
+  currency: i32,
+  market_hash_name: &'a str,
+}
+
+impl SteamMarketPriceSource {
+  pub fn new(currency: Currency) -> SteamMarketPriceSource {
+    SteamMarketPriceSource::with_base_url("https://steamcommunity.com".to_string(), currency)
+  }
+
+  // Lets the source be pointed at a local mock server
+  pub fn with_base_url(base_url: String, currency: Currency) -> SteamMarketPriceSource {
+    SteamMarketPriceSource { base_url, currency, fx: None }
+  }
+
+  // Prices are converted into the table's base currency before being returned
+  pub fn set_fx_rates(&mut self, fx: FxRateTable) {
+    self.fx = Some(fx);
+  }
+
+  pub async fn price_overview(&self, appid: i64, market_hash_name: &str) -> Option<PriceOverview> {
+    let url = format!("{}/market/priceoverview/", self.base_url);
+    let query = PriceOverviewQuery { appid, currency: self.currency.steam_id(), market_hash_name };
+
+    let client = Client::new();
+    let res = match client.get(url).query(&query).header("Accept", "application/json").send().await {
+      Ok(res) => res,
+      Err(_) => return None
+    };
+
+    if res.status() != StatusCode::OK {
+      return None;
+    }
+
+    let text = res.text().await.ok()?;
+    parse_price_overview(&text)
+  }
+}
+
+#[async_trait]
+impl PriceSource for SteamMarketPriceSource {
+  async fn price(&self, appid: i64, market_hash_name: &str) -> Option<u64> {
+    let overview = self.price_overview(appid, market_hash_name).await?;
+
+    let price = overview.lowest_price.or(overview.median_price)?;
+    let money = Money::parse(&price, self.currency)?;
+
+    let money = match &self.fx {
+      Some(fx) => fx.convert(money, fx.base)?,
+      None => money,
+    };
+
+    u64::try_from(money.amount).ok()
+  }
+}
+
+pub fn parse_price_overview(text: &str) -> Option<PriceOverview> {
+  match serde_json::from_str::<PriceOverview>(text) {
+    Ok(overview) if overview.success => Some(overview),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_price_overview() {
+    let overview = parse_price_overview(r#"{"success":true,"lowest_price":"$1.23","volume":"1,024","median_price":"$1.20"}"#).unwrap();
+    assert_eq!(overview.lowest_price, Some("$1.23".to_string()));
+    assert_eq!(overview.volume, Some("1,024".to_string()));
+
+    let sparse = parse_price_overview(r#"{"success":true}"#).unwrap();
+    assert_eq!(sparse.lowest_price, None);
+
+    assert_eq!(parse_price_overview(r#"{"success":false}"#), None);
+    assert_eq!(parse_price_overview("<html>"), None);
+  }
+
+  // Serves one canned priceoverview response from a local socket
+  async fn mock_market(body: &'static str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+      let (mut socket, _) = listener.accept().await.unwrap();
+      let mut request = [0u8; 4096];
+      let _ = socket.read(&mut request).await;
+
+      let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+      socket.write_all(response.as_bytes()).await.unwrap();
+    });
+
+    format!("http://{}", address)
+  }
+
+  #[tokio::test]
+  async fn prices_from_a_mock_market() {
+    let base_url = mock_market(r#"{"success":true,"lowest_price":"$1,234.56","volume":"3","median_price":"$1,200.00"}"#).await;
+    let source = SteamMarketPriceSource::with_base_url(base_url, Currency::USD);
+
+    assert_eq!(source.price(730, "★ Karambit | Fade (Factory New)").await, Some(123456));
+  }
+
+  #[tokio::test]
+  async fn falls_back_to_median_price() {
+    let base_url = mock_market(r#"{"success":true,"median_price":"1,23€"}"#).await;
+    let source = SteamMarketPriceSource::with_base_url(base_url, Currency::EUR);
+
+    assert_eq!(source.price(730, "Sticker | Crown (Foil)").await, Some(123));
+  }
+
+  // Points at a port nothing listens on, the source must give up rather than panic
+  #[tokio::test]
+  async fn unreachable_market_has_no_price() {
+    let source = SteamMarketPriceSource::with_base_url("http://127.0.0.1:9".to_string(), Currency::USD);
+    assert_eq!(source.price(730, "Mann Co. Supply Crate Key").await, None);
+  }
+}
@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fs;
+use async_trait::async_trait;
+
+pub mod cache;
+pub mod market;
+pub mod money;
+
+// Prices are in cents of the base currency
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+  async fn price(&self, appid: i64, market_hash_name: &str) -> Option<u64>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PriceSourceError {
+  Io(String),
+  Parse(String),
+}
+
+// In memory prices, also backs json price-list files and offline mocks
+#[derive(Clone, Debug, PartialEq)]
+pub struct StaticPriceSource {
+  prices: HashMap<(i64, String), u64>,
+}
+
+pub struct ChainedPriceSource {
+  sources: Vec<Box<dyn PriceSource>>,
+}
+
+impl StaticPriceSource {
+  pub fn new() -> StaticPriceSource {
+    StaticPriceSource { prices: HashMap::new() }
+  }
+
+  // Expects an object of market_hash_name -> price in cents
+  pub fn from_json(appid: i64, json: &str) -> Result<StaticPriceSource, PriceSourceError> {
+    let prices = match serde_json::from_str::<HashMap<String, u64>>(json) {
+      Ok(prices) => prices,
+      Err(e) => return Err(PriceSourceError::Parse(e.to_string()))
+    };
+
+    let mut source = StaticPriceSource::new();
+    for (market_hash_name, price) in prices {
+      source.insert(appid, market_hash_name, price);
+    }
+
+    Ok(source)
+  }
+
+  pub fn load(appid: i64, path: &str) -> Result<StaticPriceSource, PriceSourceError> {
+    match fs::read_to_string(path) {
+      Ok(json) => StaticPriceSource::from_json(appid, &json),
+      Err(e) => Err(PriceSourceError::Io(e.to_string()))
+    }
+  }
+
+  pub fn insert(&mut self, appid: i64, market_hash_name: String, price: u64) {
+    self.prices.insert((appid, market_hash_name), price);
+  }
+
+  pub fn extend(&mut self, other: StaticPriceSource) {
+    self.prices.extend(other.prices);
+  }
+}
+
+#[async_trait]
+impl PriceSource for StaticPriceSource {
+  async fn price(&self, appid: i64, market_hash_name: &str) -> Option<u64> {
+    self.prices.get(&(appid, market_hash_name.to_string())).copied()
+  }
+}
+
+impl ChainedPriceSource {
+  pub fn new(sources: Vec<Box<dyn PriceSource>>) -> ChainedPriceSource {
+    ChainedPriceSource { sources }
+  }
+
+  pub fn push(&mut self, source: Box<dyn PriceSource>) {
+    self.sources.push(source);
+  }
+}
+
+// First source with a price wins
+#[async_trait]
+impl PriceSource for ChainedPriceSource {
+  async fn price(&self, appid: i64, market_hash_name: &str) -> Option<u64> {
+    for source in &self.sources {
+      if let Some(price) = source.price(appid, market_hash_name).await {
+        return Some(price);
+      }
+    }
+
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn source(prices: &[(&str, u64)]) -> StaticPriceSource {
+    let mut source = StaticPriceSource::new();
+    for (name, price) in prices {
+      source.insert(730, name.to_string(), *price);
+    }
+    source
+  }
+
+  #[tokio::test]
+  async fn static_source_is_keyed_by_app_and_name() {
+    let mut prices = source(&[("Mann Co. Supply Crate Key", 250)]);
+    prices.insert(440, "Mann Co. Supply Crate Key".to_string(), 199);
+
+    assert_eq!(prices.price(730, "Mann Co. Supply Crate Key").await, Some(250));
+    assert_eq!(prices.price(440, "Mann Co. Supply Crate Key").await, Some(199));
+    assert_eq!(prices.price(570, "Mann Co. Supply Crate Key").await, None);
+    assert_eq!(prices.price(730, "mann co. supply crate key").await, None);
+  }
+
+  #[tokio::test]
+  async fn static_source_from_json() {
+    let prices = StaticPriceSource::from_json(730, r#"{ "AK-47 | Redline (Field-Tested)": 1000, "Sticker | Crown (Foil)": 100 }"#).unwrap();
+
+    assert_eq!(prices.price(730, "AK-47 | Redline (Field-Tested)").await, Some(1000));
+    assert_eq!(prices.price(730, "Sticker | Crown (Foil)").await, Some(100));
+
+    assert!(matches!(StaticPriceSource::from_json(730, r#"{ "AK-47": "ten dollars" }"#), Err(PriceSourceError::Parse(_))));
+    assert!(matches!(StaticPriceSource::load(730, "/nonexistent/prices.json"), Err(PriceSourceError::Io(_))));
+  }
+
+  #[tokio::test]
+  async fn static_source_extend_overrides() {
+    let mut prices = source(&[("a", 1), ("b", 2)]);
+    prices.extend(source(&[("b", 3), ("c", 4)]));
+
+    assert_eq!(prices.price(730, "a").await, Some(1));
+    assert_eq!(prices.price(730, "b").await, Some(3));
+    assert_eq!(prices.price(730, "c").await, Some(4));
+  }
+
+  #[tokio::test]
+  async fn chained_source_falls_back_in_order() {
+    let chain = ChainedPriceSource::new(vec![
+      Box::new(source(&[("a", 1)])),
+      Box::new(source(&[("a", 10), ("b", 20)])),
+    ]);
+
+    assert_eq!(chain.price(730, "a").await, Some(1));
+    assert_eq!(chain.price(730, "b").await, Some(20));
+    assert_eq!(chain.price(730, "c").await, None);
+
+    let mut chain = chain;
+    chain.push(Box::new(source(&[("c", 300)])));
+    assert_eq!(chain.price(730, "c").await, Some(300));
+
+    assert_eq!(ChainedPriceSource::new(Vec::new()).price(730, "a").await, None);
+  }
+}
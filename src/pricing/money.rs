@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Currency {
+  USD, GBP, EUR, CHF, RUB, PLN, BRL, JPY, NOK, IDR,
+  MYR, PHP, SGD, THB, VND, KRW, TRY, UAH, MXN, CAD,
+  AUD, NZD, CNY, INR, CLP, PEN, COP, ZAR, HKD, TWD,
+  SAR, AED, ARS, ILS, KZT, KWD, QAR, CRC, UYU,
+}
+
+// Amounts are in hundredths of the currency, the way Steam reports every wallet currency
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Money {
+  pub amount: i64,
+  pub currency: Currency,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FxRateTable {
+  pub base: Currency,
+  // Units of each currency per one unit of base
+  pub rates: HashMap<Currency, f64>,
+}
+
+pub trait FxRates {
+  fn rate(&self, from: Currency, to: Currency) -> Option<f64>;
+
+  fn convert(&self, money: Money, to: Currency) -> Option<Money> {
+    let rate = self.rate(money.currency, to)?;
+    Some(Money { amount: (money.amount as f64 * rate).round() as i64, currency: to })
+  }
+}
+
+const CURRENCIES: [(Currency, i32, &str); 39] = [
+  (Currency::USD, 1, "$"), (Currency::GBP, 2, "£"), (Currency::EUR, 3, "€"), (Currency::CHF, 4, "CHF"),
+  (Currency::RUB, 5, "pуб."), (Currency::PLN, 6, "zł"), (Currency::BRL, 7, "R$"), (Currency::JPY, 8, "¥"),
+  (Currency::NOK, 9, "kr"), (Currency::IDR, 10, "Rp"), (Currency::MYR, 11, "RM"), (Currency::PHP, 12, "P"),
+  (Currency::SGD, 13, "S$"), (Currency::THB, 14, "฿"), (Currency::VND, 15, "₫"), (Currency::KRW, 16, "₩"),
+  (Currency::TRY, 17, "TL"), (Currency::UAH, 18, "₴"), (Currency::MXN, 19, "Mex$"), (Currency::CAD, 20, "CDN$"),
+  (Currency::AUD, 21, "A$"), (Currency::NZD, 22, "NZ$"), (Currency::CNY, 23, "¥"), (Currency::INR, 24, "₹"),
+  (Currency::CLP, 25, "CLP$"), (Currency::PEN, 26, "S/."), (Currency::COP, 27, "COL$"), (Currency::ZAR, 28, "R"),
+  (Currency::HKD, 29, "HK$"), (Currency::TWD, 30, "NT$"), (Currency::SAR, 31, "SR"), (Currency::AED, 32, "AED"),
+  (Currency::ARS, 34, "ARS$"), (Currency::ILS, 35, "₪"), (Currency::KZT, 37, "₸"), (Currency::KWD, 38, "KD"),
+  (Currency::QAR, 39, "QR"), (Currency::CRC, 40, "₡"), (Currency::UYU, 41, "$U"),
+];
+
+impl Currency {
+  pub fn from_steam_id(id: i32) -> Option<Currency> {
+    CURRENCIES.iter().find(|c| c.1 == id).map(|c| c.0)
+  }
+
+  pub fn steam_id(self) -> i32 {
+    CURRENCIES.iter().find(|c| c.0 == self).map(|c| c.1).unwrap()
+  }
+
+  pub fn symbol(self) -> &'static str {
+    CURRENCIES.iter().find(|c| c.0 == self).map(|c| c.2).unwrap()
+  }
+
+  // Digits Steam prints after the decimal separator
+  pub fn decimals(self) -> usize {
+    match self {
+      Currency::KWD => 3,
+      _ => 2,
+    }
+  }
+
+  pub fn code(self) -> String {
+    format!("{:?}", self)
+  }
+
+  pub fn from_code(code: &str) -> Option<Currency> {
+    CURRENCIES.iter().find(|c| c.0.code() == code.to_uppercase()).map(|c| c.0)
+  }
+
+  // Longest symbol wins so "CDN$ 1.23" is not read as dollars; ambiguous symbols resolve to the first listed
+  pub fn detect(price: &str) -> Option<Currency> {
+    CURRENCIES.iter()
+      .filter(|c| price.contains(c.2))
+      .max_by(|a, b| a.2.chars().count().cmp(&b.2.chars().count()).then(b.1.cmp(&a.1)))
+      .map(|c| c.0)
+  }
+}
+
+impl Money {
+  pub fn new(amount: i64, currency: Currency) -> Money {
+    Money { amount, currency }
+  }
+
+  // Parses Steam's formatted prices such as "$4.56", "1,23€", "1.234,56€", "12,--€" or "¥ 123".
+  // Kuwaiti dinars have three decimals, the last one is rounded away
+  pub fn parse(price: &str, currency: Currency) -> Option<Money> {
+    Some(Money { amount: parse_amount(price, currency.decimals())?, currency })
+  }
+
+  pub fn parse_detect(price: &str) -> Option<Money> {
+    Money::parse(price, Currency::detect(price)?)
+  }
+
+  pub fn convert(self, to: Currency, rates: &dyn FxRates) -> Option<Money> {
+    rates.convert(self, to)
+  }
+}
+
+fn parse_amount(price: &str, decimals: usize) -> Option<i64> {
+  let negative = price.trim_start().starts_with('-');
+
+  // "--" stands in for zero cents
+  let price = price.replace("--", "00");
+
+  let numeric = price.chars()
+    .skip_while(|c| !c.is_ascii_digit())
+    .collect::<String>();
+  let numeric = numeric.trim_end_matches(|c: char| !c.is_ascii_digit());
+
+  if numeric.is_empty() {
+    return None;
+  }
+
+  let separator = numeric.rfind([',', '.']);
+
+  // A trailing group of up to `decimals` digits is the decimal part, anything longer is a thousands group.
+  // For three decimal currencies that means "1,234 KD" reads as 1.234, Steam always prints all three decimals
+  let (whole, fraction) = match separator {
+    Some(i) => {
+      let fraction = &numeric[i + 1..];
+      match fraction.len() {
+        len if len >= 1 && len <= decimals && fraction.chars().all(|c| c.is_ascii_digit()) => (&numeric[..i], fraction),
+        _ => (numeric, ""),
+      }
+    },
+    None => (numeric, ""),
+  };
+
+  let whole = whole.chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+  let whole = match whole.is_empty() {
+    true => 0,
+    false => whole.parse::<i64>().ok()?,
+  };
+
+  // Scale to hundredths, rounding half up
+  let fraction = match fraction.len() {
+    0 => 0,
+    len => {
+      let scale = 10_i64.pow(len as u32);
+      (fraction.parse::<i64>().ok()? * 200 + scale) / (2 * scale)
+    },
+  };
+
+  let amount = whole * 100 + fraction;
+
+  match negative {
+    true => Some(-amount),
+    false => Some(amount),
+  }
+}
+
+impl FxRateTable {
+  pub fn new(base: Currency) -> FxRateTable {
+    FxRateTable { base, rates: HashMap::new() }
+  }
+
+  pub fn set_rate(&mut self, currency: Currency, rate: f64) {
+    self.rates.insert(currency, rate);
+  }
+
+  fn per_base(&self, currency: Currency) -> Option<f64> {
+    match currency == self.base {
+      true => Some(1.0),
+      false => self.rates.get(&currency).copied().filter(|r| *r > 0.0),
+    }
+  }
+}
+
+impl FxRates for FxRateTable {
+  fn rate(&self, from: Currency, to: Currency) -> Option<f64> {
+    Some(self.per_base(to)? / self.per_base(from)?)
+  }
+}
+
+impl fmt::Display for Money {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let sign = if self.amount < 0 { "-" } else { "" };
+    write!(f, "{}{}.{:02} {}", sign, self.amount.abs() / 100, self.amount.abs() % 100, self.currency.code())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_steam_price_formats() {
+    assert_eq!(Money::parse("$4.56", Currency::USD), Some(Money::new(456, Currency::USD)));
+    assert_eq!(Money::parse("1,23€", Currency::EUR), Some(Money::new(123, Currency::EUR)));
+    assert_eq!(Money::parse("1.234,56€", Currency::EUR), Some(Money::new(123456, Currency::EUR)));
+    assert_eq!(Money::parse("12,--€", Currency::EUR), Some(Money::new(1200, Currency::EUR)));
+    assert_eq!(Money::parse("¥ 123", Currency::JPY), Some(Money::new(12300, Currency::JPY)));
+    assert_eq!(Money::parse("1 234,56 pуб.", Currency::RUB), Some(Money::new(123456, Currency::RUB)));
+    assert_eq!(Money::parse("R$ 4,56", Currency::BRL), Some(Money::new(456, Currency::BRL)));
+    assert_eq!(Money::parse("CDN$ 1.23", Currency::CAD), Some(Money::new(123, Currency::CAD)));
+  }
+
+  #[test]
+  fn parses_edge_cases() {
+    assert_eq!(Money::parse("$1,234", Currency::USD), Some(Money::new(123400, Currency::USD)));
+    assert_eq!(Money::parse("$1,234.5", Currency::USD), Some(Money::new(123450, Currency::USD)));
+    assert_eq!(Money::parse("$0.05", Currency::USD), Some(Money::new(5, Currency::USD)));
+    assert_eq!(Money::parse("-$4.56", Currency::USD), Some(Money::new(-456, Currency::USD)));
+    assert_eq!(Money::parse("$", Currency::USD), None);
+    assert_eq!(Money::parse("", Currency::USD), None);
+  }
+
+  #[test]
+  fn parses_three_decimal_currencies() {
+    assert_eq!(Currency::KWD.decimals(), 3);
+    assert_eq!(Money::parse("1.234 KD", Currency::KWD), Some(Money::new(123, Currency::KWD)));
+    assert_eq!(Money::parse("0.875 KD", Currency::KWD), Some(Money::new(88, Currency::KWD)));
+    assert_eq!(Money::parse("1,234.500 KD", Currency::KWD), Some(Money::new(123450, Currency::KWD)));
+    assert_eq!(Money::parse("12.5 KD", Currency::KWD), Some(Money::new(1250, Currency::KWD)));
+
+    // The same text in a two decimal currency is a thousands group
+    assert_eq!(Money::parse("1.234", Currency::USD), Some(Money::new(123400, Currency::USD)));
+  }
+
+  #[test]
+  fn detects_currency_from_symbol() {
+    assert_eq!(Currency::detect("CDN$ 1.23"), Some(Currency::CAD));
+    assert_eq!(Currency::detect("$4.56"), Some(Currency::USD));
+    assert_eq!(Currency::detect("R$ 4,56"), Some(Currency::BRL));
+    assert_eq!(Currency::detect("1,23€"), Some(Currency::EUR));
+    assert_eq!(Money::parse_detect("1 234,56 pуб."), Some(Money::new(123456, Currency::RUB)));
+    assert_eq!(Money::parse_detect("1.234 KD"), Some(Money::new(123, Currency::KWD)));
+  }
+
+  #[test]
+  fn converts_through_the_base() {
+    let mut rates = FxRateTable::new(Currency::USD);
+    rates.set_rate(Currency::EUR, 0.5);
+    rates.set_rate(Currency::GBP, 0.25);
+
+    assert_eq!(Money::new(100, Currency::USD).convert(Currency::EUR, &rates), Some(Money::new(50, Currency::EUR)));
+    assert_eq!(Money::new(100, Currency::EUR).convert(Currency::GBP, &rates), Some(Money::new(50, Currency::GBP)));
+    assert_eq!(Money::new(100, Currency::EUR).convert(Currency::JPY, &rates), None);
+    assert_eq!(Money::new(-123456, Currency::EUR).to_string(), "-1234.56 EUR");
+  }
+}
@@ -0,0 +1,319 @@
+use std::fs;
+use serde::{Deserialize, Serialize};
+use crate::steam::offers::{Offer, OfferItem};
+use crate::steam::steam_id::SteamId;
+use crate::steam::Trade::TradeHoldDurations;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RulesEngine {
+  #[serde(default = "RuleAction::hold")]
+  pub default: RuleAction,
+  pub rules: Vec<Rule>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Rule {
+  pub name: String,
+  pub when: Condition,
+  pub action: RuleAction,
+  pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum RuleAction {
+  Accept,
+  Decline,
+  Hold,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type")]
+pub enum Condition {
+  Donation,
+  PartnerIn { steam_ids: Vec<SteamId> },
+  Escrow,
+  GivesTag { category: String, tag: String },
+  ReceivesTag { category: String, tag: String },
+  ValueMatched {
+    #[serde(default = "default_ratio")]
+    min_ratio: f64
+  },
+  All { conditions: Vec<Condition> },
+  Any { conditions: Vec<Condition> },
+  Not { condition: Box<Condition> },
+}
+
+// Values are in cents, filled in by whatever prices the offer
+pub struct OfferContext<'a> {
+  pub offer: &'a Offer,
+  pub escrow: Option<&'a TradeHoldDurations>,
+  pub our_value: Option<u64>,
+  pub their_value: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Decision {
+  pub action: RuleAction,
+  pub rule: Option<String>,
+  pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RulesError {
+  Io(String),
+  Parse(String),
+}
+
+fn default_ratio() -> f64 {
+  1.0
+}
+
+impl RuleAction {
+  fn hold() -> RuleAction {
+    RuleAction::Hold
+  }
+}
+
+impl RulesEngine {
+  pub fn new(rules: Vec<Rule>, default: RuleAction) -> RulesEngine {
+    RulesEngine { default, rules }
+  }
+
+  pub fn load(path: &str) -> Result<RulesEngine, RulesError> {
+    let text = match fs::read_to_string(path) {
+      Ok(text) => text,
+      Err(e) => return Err(RulesError::Io(e.to_string()))
+    };
+
+    RulesEngine::parse(&text)
+  }
+
+  pub fn parse(config: &str) -> Result<RulesEngine, RulesError> {
+    match serde_json::from_str::<RulesEngine>(config) {
+      Ok(engine) => Ok(engine),
+      Err(e) => Err(RulesError::Parse(e.to_string()))
+    }
+  }
+
+  // Rules are checked in order, the first match decides
+  pub fn evaluate(&self, context: &OfferContext) -> Decision {
+    for rule in &self.rules {
+      if rule.when.matches(context) {
+        let reason = match &rule.reason {
+          Some(reason) => reason.to_owned(),
+          None => rule.when.describe(),
+        };
+
+        return Decision { action: rule.action, rule: Some(rule.name.to_owned()), reason };
+      }
+    }
+
+    Decision { action: self.default, rule: None, reason: "no rule matched".to_string() }
+  }
+}
+
+impl Condition {
+  pub fn matches(&self, context: &OfferContext) -> bool {
+    let offer = context.offer;
+
+    match self {
+      Condition::Donation => offer.items_to_give.is_empty() && !offer.items_to_receive.is_empty(),
+      Condition::PartnerIn { steam_ids } => steam_ids.contains(&offer.partner),
+      Condition::Escrow => {
+        let held = match context.escrow {
+          Some(escrow) => escrow.has_hold(),
+          None => false,
+        };
+        held || offer.escrow_end_date > 0
+      },
+      Condition::GivesTag { category, tag } => has_tag(&offer.items_to_give, category, tag),
+      Condition::ReceivesTag { category, tag } => has_tag(&offer.items_to_receive, category, tag),
+      Condition::ValueMatched { min_ratio } => match (context.our_value, context.their_value) {
+        // Values are whole cents, the slack keeps ratios like 1.1 from rounding 110 above 110
+        (Some(ours), Some(theirs)) => theirs as f64 >= ours as f64 * min_ratio - 1e-6,
+        _ => false,
+      },
+      Condition::All { conditions } => conditions.iter().all(|c| c.matches(context)),
+      Condition::Any { conditions } => conditions.iter().any(|c| c.matches(context)),
+      Condition::Not { condition } => !condition.matches(context),
+    }
+  }
+
+  pub fn describe(&self) -> String {
+    match self {
+      Condition::Donation => "we only receive items".to_string(),
+      Condition::PartnerIn { .. } => "partner is whitelisted".to_string(),
+      Condition::Escrow => "trade would be held in escrow".to_string(),
+      Condition::GivesTag { category, tag } => format!("we give an item tagged {} {}", category, tag),
+      Condition::ReceivesTag { category, tag } => format!("we receive an item tagged {} {}", category, tag),
+      Condition::ValueMatched { min_ratio } => format!("their value is at least {}x ours", min_ratio),
+      Condition::All { conditions } => conditions.iter().map(|c| c.describe()).collect::<Vec<String>>().join(" and "),
+      Condition::Any { conditions } => conditions.iter().map(|c| c.describe()).collect::<Vec<String>>().join(" or "),
+      Condition::Not { condition } => format!("not ({})", condition.describe()),
+    }
+  }
+}
+
+fn has_tag(items: &[OfferItem], category: &String, tag: &String) -> bool {
+  items.iter()
+    .filter_map(|i| i.description.as_ref())
+    .flat_map(|d| d.tags.iter())
+    .any(|t| {
+      (&t.category == category || &t.localized_category_name == category) &&
+      (&t.localized_tag_name == tag || &t.internal_name == tag)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::{description, offer, offer_item};
+
+  fn covert(assetid: &str) -> OfferItem {
+    offer_item(assetid, Some(description("1", "AK-47 | Fire Serpent (Field-Tested)", &[("Rarity", "Covert"), ("Type", "Rifle")])))
+  }
+
+  fn sticker(assetid: &str) -> OfferItem {
+    offer_item(assetid, Some(description("2", "Sticker | Crown (Foil)", &[("Rarity", "Extraordinary"), ("Type", "Sticker")])))
+  }
+
+  fn context(offer: &Offer) -> OfferContext<'_> {
+    OfferContext { offer, escrow: None, our_value: None, their_value: None }
+  }
+
+  // The examples from the request, written the way a config file would
+  const CONFIG: &str = r#"{
+    "default": "Hold",
+    "rules": [
+      { "name": "donations", "when": { "type": "Donation" }, "action": "Accept", "reason": null },
+      { "name": "whitelist", "when": { "type": "PartnerIn", "steam_ids": ["76561197960287930"] }, "action": "Accept", "reason": "trusted partner" },
+      { "name": "escrow", "when": { "type": "Escrow" }, "action": "Decline", "reason": null },
+      {
+        "name": "covert",
+        "when": { "type": "All", "conditions": [
+          { "type": "GivesTag", "category": "Rarity", "tag": "Covert" },
+          { "type": "Not", "condition": { "type": "ValueMatched" } }
+        ] },
+        "action": "Decline",
+        "reason": null
+      }
+    ]
+  }"#;
+
+  #[test]
+  fn donation() {
+    let donation = offer(vec![], vec![sticker("10")]);
+    let swap = offer(vec![sticker("1")], vec![sticker("10")]);
+    let empty = offer(vec![], vec![]);
+
+    assert!(Condition::Donation.matches(&context(&donation)));
+    assert!(!Condition::Donation.matches(&context(&swap)));
+    assert!(!Condition::Donation.matches(&context(&empty)));
+  }
+
+  #[test]
+  fn partner_in() {
+    let offer = offer(vec![sticker("1")], vec![]);
+    let listed = Condition::PartnerIn { steam_ids: vec![SteamId::from_account_id(22202)] };
+    let other = Condition::PartnerIn { steam_ids: vec![SteamId::from_account_id(1)] };
+
+    assert!(listed.matches(&context(&offer)));
+    assert!(!other.matches(&context(&offer)));
+  }
+
+  #[test]
+  fn escrow() {
+    let mut held = offer(vec![sticker("1")], vec![]);
+    let clear = held.to_owned();
+    held.escrow_end_date = 1700000000;
+
+    assert!(Condition::Escrow.matches(&context(&held)));
+    assert!(!Condition::Escrow.matches(&context(&clear)));
+
+    let durations = TradeHoldDurations { my_escrow_seconds: 0, their_escrow_seconds: 86400, both_escrow_seconds: 86400 };
+    let with_hold = OfferContext { offer: &clear, escrow: Some(&durations), our_value: None, their_value: None };
+    assert!(Condition::Escrow.matches(&with_hold));
+  }
+
+  #[test]
+  fn tags() {
+    let offer = offer(vec![covert("1")], vec![sticker("10")]);
+
+    let gives_covert = Condition::GivesTag { category: "Rarity".to_string(), tag: "Covert".to_string() };
+    let receives_covert = Condition::ReceivesTag { category: "Rarity".to_string(), tag: "Covert".to_string() };
+    let receives_sticker = Condition::ReceivesTag { category: "Type".to_string(), tag: "Sticker".to_string() };
+
+    assert!(gives_covert.matches(&context(&offer)));
+    assert!(!receives_covert.matches(&context(&offer)));
+    assert!(receives_sticker.matches(&context(&offer)));
+
+    let undescribed = crate::test_support::offer(vec![offer_item("1", None)], vec![]);
+    assert!(!gives_covert.matches(&context(&undescribed)));
+  }
+
+  #[test]
+  fn value_matched() {
+    let offer = offer(vec![covert("1")], vec![sticker("10")]);
+    let condition = Condition::ValueMatched { min_ratio: 1.1 };
+
+    let valued = |ours, theirs| OfferContext { offer: &offer, escrow: None, our_value: ours, their_value: theirs };
+
+    assert!(condition.matches(&valued(Some(100), Some(110))));
+    assert!(!condition.matches(&valued(Some(100), Some(109))));
+    assert!(!condition.matches(&valued(None, Some(110))));
+    assert!(!condition.matches(&valued(Some(100), None)));
+  }
+
+  #[test]
+  fn combinators() {
+    let offer = offer(vec![], vec![sticker("10")]);
+    let yes = Condition::Donation;
+    let no = Condition::Escrow;
+
+    assert!(Condition::All { conditions: vec![yes.to_owned(), yes.to_owned()] }.matches(&context(&offer)));
+    assert!(!Condition::All { conditions: vec![yes.to_owned(), no.to_owned()] }.matches(&context(&offer)));
+    assert!(Condition::Any { conditions: vec![no.to_owned(), yes.to_owned()] }.matches(&context(&offer)));
+    assert!(!Condition::Any { conditions: vec![no.to_owned()] }.matches(&context(&offer)));
+    assert!(Condition::Not { condition: Box::new(no) }.matches(&context(&offer)));
+    assert!(!Condition::Not { condition: Box::new(yes) }.matches(&context(&offer)));
+  }
+
+  #[test]
+  fn covert_unless_value_matched() {
+    let engine = RulesEngine::parse(CONFIG).unwrap();
+    let mut offer = offer(vec![covert("1")], vec![sticker("10")]);
+    offer.partner = SteamId::from_account_id(1);
+
+    let underpaid = OfferContext { offer: &offer, escrow: None, our_value: Some(10000), their_value: Some(5000) };
+    let decision = engine.evaluate(&underpaid);
+    assert_eq!(decision.action, RuleAction::Decline);
+    assert_eq!(decision.rule, Some("covert".to_string()));
+    assert_eq!(decision.reason, "we give an item tagged Rarity Covert and not (their value is at least 1x ours)");
+
+    let matched = OfferContext { offer: &offer, escrow: None, our_value: Some(10000), their_value: Some(10000) };
+    let decision = engine.evaluate(&matched);
+    assert_eq!(decision.action, RuleAction::Hold);
+    assert_eq!(decision.rule, None);
+  }
+
+  #[test]
+  fn first_matching_rule_wins() {
+    let engine = RulesEngine::parse(CONFIG).unwrap();
+
+    let mut whitelisted = offer(vec![covert("1")], vec![]);
+    whitelisted.escrow_end_date = 1700000000;
+    let decision = engine.evaluate(&context(&whitelisted));
+    assert_eq!(decision.action, RuleAction::Accept);
+    assert_eq!(decision.reason, "trusted partner");
+
+    let mut held = whitelisted.to_owned();
+    held.partner = SteamId::from_account_id(1);
+    assert_eq!(engine.evaluate(&context(&held)).rule, Some("escrow".to_string()));
+  }
+
+  #[test]
+  fn rejects_bad_config() {
+    assert!(matches!(RulesEngine::parse(r#"{ "rules": [{ "name": "x", "when": { "type": "Nope" }, "action": "Accept" }] }"#), Err(RulesError::Parse(_))));
+    assert!(matches!(RulesEngine::load("/nonexistent/rules.json"), Err(RulesError::Io(_))));
+  }
+}
@@ -7,6 +7,7 @@ use base64::{Engine as _, engine::general_purpose};
 use dotenv;
 use steam_guard;
 use super::Inventory::UnauthorizedResponse;
+use super::steam_id::SteamId;
 
 use num::{BigInt, Num};
 
@@ -30,7 +31,7 @@ struct TransferParameters {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Account { 
-  pub steam_id: String,
+  pub steam_id: SteamId,
   pub logged_in: bool,
   token_secure: String,
   auth: String,
@@ -100,8 +101,13 @@ impl Account {
       Err(e) => panic!("{}", e)
     };
 
+    let steam_id = match SteamId::parse(&login_response.transfer_parameters.steamid) {
+      Ok(id) => id,
+      Err(e) => panic!("{}", e)
+    };
+
     Ok(Account { 
-      steam_id: login_response.transfer_parameters.steamid, 
+      steam_id, 
       logged_in: login_response.login_complete, 
       token_secure: login_response.transfer_parameters.token_secure, 
       auth: login_response.transfer_parameters.auth, 
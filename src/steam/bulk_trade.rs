@@ -0,0 +1,197 @@
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use super::Trade::{IdempotentSendError, OfferAsset, SendKeyStore, TradeOffer, TradeOfferSuccess, MAX_ITEMS_PER_OFFER};
+use super::offers::{self, OfferError};
+use super::offer_state::TradeOfferState;
+use super::steam_id::SteamId;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BulkTrade {
+  pub chunks: Vec<TradeChunk>,
+  pub pacing: Duration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeChunk {
+  // Idempotency key for send_idempotent, a resent chunk finds the offer an earlier attempt created
+  pub key: String,
+  pub offer: TradeOffer,
+  pub sent: Option<TradeOfferSuccess>,
+  pub state: Option<TradeOfferState>,
+  pub error: Option<IdempotentSendError>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BulkTradeProgress {
+  pub total_chunks: usize,
+  pub sent_chunks: usize,
+  pub completed_chunks: usize,
+  pub failed_chunks: Vec<usize>,
+  pub total_items: usize,
+  pub completed_items: usize,
+}
+
+impl BulkTrade {
+  // Chunk keys are derived from key, it must be unique per bulk trade and stable across restarts
+  pub fn new(key: &str, partner: SteamId, token: Option<String>, message: String, self_assets: Vec<OfferAsset>, partner_assets: Vec<OfferAsset>) -> BulkTrade {
+    BulkTrade::with_limit(key, partner, token, message, self_assets, partner_assets, MAX_ITEMS_PER_OFFER)
+  }
+
+  pub fn with_limit(key: &str, partner: SteamId, token: Option<String>, message: String, self_assets: Vec<OfferAsset>, partner_assets: Vec<OfferAsset>, limit: usize) -> BulkTrade {
+    let limit = limit.max(1);
+    let items = self_assets.into_iter().map(|a| (true, a))
+      .chain(partner_assets.into_iter().map(|a| (false, a)))
+      .collect::<Vec<(bool, OfferAsset)>>();
+
+    let chunks = items.chunks(limit).enumerate().map(|(index, chunk)| {
+      let mut offer = TradeOffer::new_with_steam_id(partner, token.to_owned());
+      offer.set_trade_message(message.to_owned());
+
+      for (is_self, asset) in chunk {
+        match is_self {
+          true => offer.add_self_item(asset.to_owned()),
+          false => offer.add_partner_item(asset.to_owned()),
+        }
+      }
+
+      TradeChunk { key: format!("{}:{}", key, index), offer, sent: None, state: None, error: None }
+    }).collect();
+
+    BulkTrade { chunks, pacing: Duration::from_secs(10) }
+  }
+
+  pub fn set_pacing(&mut self, pacing: Duration) {
+    self.pacing = pacing;
+  }
+
+  // Sends every chunk that has not gone out yet, failed chunks are retried under the same key.
+  // Steam errors stay on their chunk, a failing key store stops the run since sends could no longer be deduplicated
+  pub async fn send(&mut self, cookie: &String, api_key: &String, store: &(dyn SendKeyStore + Sync)) -> Result<BulkTradeProgress, IdempotentSendError> {
+    let mut first = true;
+
+    for chunk in self.chunks.iter_mut().filter(|c| c.sent.is_none()) {
+      if !first {
+        tokio::time::sleep(self.pacing).await;
+      }
+      first = false;
+
+      match chunk.offer.send_idempotent(cookie, api_key, &chunk.key, store).await {
+        Ok(success) => {
+          chunk.sent = Some(success);
+          chunk.error = None;
+        },
+        Err(IdempotentSendError::Store(e)) => {
+          chunk.error = Some(IdempotentSendError::Store(e.to_owned()));
+          return Err(IdempotentSendError::Store(e));
+        },
+        Err(e) => chunk.error = Some(e),
+      }
+    }
+
+    Ok(self.progress())
+  }
+
+  pub async fn refresh(&mut self, api_key: &String) -> Result<BulkTradeProgress, OfferError> {
+    for chunk in self.chunks.iter_mut() {
+      let tradeofferid = match &chunk.sent {
+        Some(sent) => sent.tradeofferid.to_owned(),
+        None => continue,
+      };
+
+      if chunk.state.map(|s| s.is_terminal()).unwrap_or(false) {
+        continue;
+      }
+
+      if let Some(offer) = offers::get_trade_offer(api_key, &tradeofferid, false).await? {
+        chunk.state = Some(offer.state);
+      }
+    }
+
+    Ok(self.progress())
+  }
+
+  pub fn progress(&self) -> BulkTradeProgress {
+    let mut progress = BulkTradeProgress {
+      total_chunks: self.chunks.len(),
+      sent_chunks: 0,
+      completed_chunks: 0,
+      failed_chunks: Vec::new(),
+      total_items: 0,
+      completed_items: 0,
+    };
+
+    for (index, chunk) in self.chunks.iter().enumerate() {
+      let items = chunk.offer.json_tradeoffer.me.assets.len() + chunk.offer.json_tradeoffer.them.assets.len();
+      progress.total_items += items;
+
+      if chunk.sent.is_some() {
+        progress.sent_chunks += 1;
+      }
+
+      match chunk.state {
+        Some(TradeOfferState::Accepted) => {
+          progress.completed_chunks += 1;
+          progress.completed_items += items;
+        },
+        Some(state) if state.is_terminal() || state == TradeOfferState::InvalidItems => progress.failed_chunks.push(index),
+        _ => if chunk.error.is_some() { progress.failed_chunks.push(index) },
+      }
+    }
+
+    progress
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::steam::Trade::SendKeyRecord;
+
+  struct BrokenStore;
+
+  impl SendKeyStore for BrokenStore {
+    fn get_send_key(&self, _key: &str) -> Result<Option<SendKeyRecord>, String> {
+      Err("disk full".to_string())
+    }
+
+    fn begin_send_key(&self, _record: &SendKeyRecord) -> Result<(), String> {
+      Err("disk full".to_string())
+    }
+
+    fn complete_send_key(&self, _key: &str, _success: &TradeOfferSuccess) -> Result<(), String> {
+      Err("disk full".to_string())
+    }
+  }
+
+  fn assets(count: usize) -> Vec<OfferAsset> {
+    (0..count).map(|i| OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), i.to_string())).collect()
+  }
+
+  #[test]
+  fn splits_into_keyed_chunks() {
+    let bulk = BulkTrade::with_limit("move-1", SteamId::from_account_id(22202), None, "storage".to_string(), assets(5), assets(2), 3);
+
+    assert_eq!(bulk.chunks.len(), 3);
+    assert_eq!(bulk.chunks.iter().map(|c| c.key.as_str()).collect::<Vec<_>>(), vec!["move-1:0", "move-1:1", "move-1:2"]);
+    assert_eq!(bulk.chunks[1].offer.json_tradeoffer.me.assets.len(), 2);
+    assert_eq!(bulk.chunks[1].offer.json_tradeoffer.them.assets.len(), 1);
+
+    let progress = bulk.progress();
+    assert_eq!(progress.total_chunks, 3);
+    assert_eq!(progress.total_items, 7);
+    assert_eq!(progress.sent_chunks, 0);
+  }
+
+  #[tokio::test]
+  async fn store_failure_stops_the_run() {
+    let mut bulk = BulkTrade::with_limit("move-2", SteamId::from_account_id(22202), None, String::new(), assets(4), Vec::new(), 2);
+    bulk.set_pacing(Duration::from_secs(0));
+
+    let result = bulk.send(&String::new(), &String::new(), &BrokenStore).await;
+
+    assert_eq!(result, Err(IdempotentSendError::Store("disk full".to_string())));
+    assert!(bulk.chunks[0].error.is_some());
+    assert_eq!(bulk.chunks[1].error, None);
+    assert_eq!(bulk.progress().failed_chunks, vec![0]);
+  }
+}
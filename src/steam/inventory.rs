@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use reqwest::{Client, StatusCode};
 use super::Trade::OfferAsset;
+use super::steam_id::SteamId;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Inventory {
@@ -78,6 +79,7 @@ pub struct UnauthorizedResponse {
   pub error: String
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ItemRarity {
   ConsumerGrade,
   IndustrialGrade,
@@ -97,6 +99,7 @@ pub enum ItemRarity {
   Exotic
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ItemCategory {
   Normal,
   Souvenir,
@@ -105,6 +108,7 @@ pub enum ItemCategory {
   SpecialStattrak
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ItemExterior {
   FieldTested,
   MinimalWear,
@@ -114,6 +118,7 @@ pub enum ItemExterior {
   NotPainted
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ItemType {
   Pistol,
   SMG,
@@ -137,8 +142,92 @@ pub enum ItemType {
   Tool
 }
 
+impl AssetDescription {
+  pub fn tag(&self, category: &str) -> Option<&Tag> {
+    self.tags.iter().find(|t| t.category == category)
+  }
+
+  pub fn rarity(&self) -> Option<ItemRarity> {
+    self.tag("Rarity").and_then(|t| ItemRarity::from_tag_name(&t.localized_tag_name))
+  }
+
+  pub fn exterior(&self) -> Option<ItemExterior> {
+    self.tag("Exterior").and_then(|t| ItemExterior::from_tag_name(&t.localized_tag_name))
+  }
+
+  pub fn item_type(&self) -> Option<ItemType> {
+    self.tag("Type").and_then(|t| ItemType::from_tag_name(&t.localized_tag_name))
+  }
+}
+
+impl ItemRarity {
+  pub fn from_tag_name(name: &str) -> Option<ItemRarity> {
+    match name {
+      "Base Grade" => Some(ItemRarity::BaseGrade),
+      "Consumer Grade" => Some(ItemRarity::ConsumerGrade),
+      "Industrial Grade" => Some(ItemRarity::IndustrialGrade),
+      "Mil-Spec Grade" => Some(ItemRarity::MilspecGrade),
+      "Distinguished" => Some(ItemRarity::Distinguished),
+      "High Grade" => Some(ItemRarity::HighGrade),
+      "Restricted" => Some(ItemRarity::Restricted),
+      "Exceptional" => Some(ItemRarity::Exceptional),
+      "Remarkable" => Some(ItemRarity::Remarkable),
+      "Classified" => Some(ItemRarity::Classified),
+      "Superior" => Some(ItemRarity::Superior),
+      "Exotic" => Some(ItemRarity::Exotic),
+      "Covert" => Some(ItemRarity::Covert),
+      "Extraordinary" => Some(ItemRarity::Extraordinary),
+      "Master" => Some(ItemRarity::Master),
+      "Contraband" => Some(ItemRarity::Contraband),
+      _ => None
+    }
+  }
+}
+
+impl ItemExterior {
+  pub fn from_tag_name(name: &str) -> Option<ItemExterior> {
+    match name {
+      "Factory New" => Some(ItemExterior::FactoryNew),
+      "Minimal Wear" => Some(ItemExterior::MinimalWear),
+      "Field-Tested" => Some(ItemExterior::FieldTested),
+      "Well-Worn" => Some(ItemExterior::WellWorn),
+      "Battle-Scarred" => Some(ItemExterior::BattleScarred),
+      "Not Painted" => Some(ItemExterior::NotPainted),
+      _ => None
+    }
+  }
+}
+
+impl ItemType {
+  pub fn from_tag_name(name: &str) -> Option<ItemType> {
+    match name {
+      "Pistol" => Some(ItemType::Pistol),
+      "SMG" => Some(ItemType::SMG),
+      "Rifle" => Some(ItemType::Rifle),
+      "Sniper Rifle" => Some(ItemType::SniperRifle),
+      "Shotgun" => Some(ItemType::Shotgun),
+      "Machinegun" => Some(ItemType::Machinegun),
+      "Agent" => Some(ItemType::Agent),
+      "Container" => Some(ItemType::Container),
+      "Knife" => Some(ItemType::Knife),
+      "Sticker" => Some(ItemType::Sticker),
+      "Gloves" => Some(ItemType::Gloves),
+      "Graffiti" => Some(ItemType::Graffiti),
+      "Music Kit" => Some(ItemType::MusicKit),
+      "Patch" => Some(ItemType::Patch),
+      "Collectible" => Some(ItemType::Collectible),
+      "Key" => Some(ItemType::Key),
+      "Pass" => Some(ItemType::Pass),
+      "Gift" => Some(ItemType::Gift),
+      "Tag" => Some(ItemType::Tag),
+      "Tool" => Some(ItemType::Tool),
+      _ => None
+    }
+  }
+}
+
 impl Inventory {
-  pub async fn new(steam_id: String, game_id: String, context_id: String) -> Result<Inventory, UnauthorizedResponse> {
+  pub async fn new(steam_id: SteamId, game_id: String, context_id: String) -> Result<Inventory, UnauthorizedResponse> {
     
     let url = format!("https://steamcommunity.com/inventory/{}/{}/{}?l=english", steam_id, game_id, context_id);
 
@@ -174,7 +263,7 @@ impl Inventory {
       for asset in &self.assets {
         let has_seen = seen.get(&asset.assetid).unwrap_or(&false);
         if !has_seen && item.classid == asset.classid && item.instanceid == asset.instanceid {
-          assets.push(OfferAsset::new("730".to_string(), asset.contextid.to_owned(), "1".to_string(), asset.assetid.to_owned()));
+          assets.push(OfferAsset::new(asset.appid.to_string(), asset.contextid.to_owned(), "1".to_string(), asset.assetid.to_owned()));
           seen.insert(&asset.assetid, true);
         }
       }
@@ -183,6 +272,14 @@ impl Inventory {
     assets
   }
 
+  pub fn get_description(&self, offer_asset: &OfferAsset) -> Option<AssetDescription> {
+    let asset = self.assets.iter().find(|a| a.assetid == offer_asset.assetid && a.contextid == offer_asset.contextid)?;
+
+    self.descriptions.iter()
+      .find(|d| d.classid == asset.classid && d.instanceid == asset.instanceid)
+      .cloned()
+  }
+
   pub fn search_item_name(&self, item_name: String) -> Option<AssetDescription> {
     for description in &self.descriptions {
       if description.market_name.contains(item_name.as_str()) {
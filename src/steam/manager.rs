@@ -0,0 +1,227 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use super::offers::{self, GetTradeOffersOptions, Offer, OfferError};
+use super::offer_state::{TradeOfferState, TradeOfferStateTracker};
+
+// Offers updated right before a poll can be missed if the cutoff is exact
+const CUTOFF_MARGIN_SECS: u64 = 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TradeOfferEvent {
+  NewOffer(Offer),
+  SentOfferChanged { offer: Offer, old_state: TradeOfferState },
+  ReceivedOfferChanged { offer: Offer, old_state: TradeOfferState },
+  NeedsConfirmation(Offer),
+  ItemsReceived(Offer),
+  PollFailed(OfferError),
+}
+
+pub struct TradeOfferManager {
+  api_key: String,
+  interval: Duration,
+  tracker: Arc<Mutex<TradeOfferStateTracker>>,
+  time_historical_cutoff: Option<u64>,
+  sender: mpsc::Sender<TradeOfferEvent>,
+}
+
+impl TradeOfferManager {
+  pub fn new(api_key: String, interval: Duration) -> (TradeOfferManager, mpsc::Receiver<TradeOfferEvent>) {
+    let (sender, receiver) = mpsc::channel(100);
+
+    let manager = TradeOfferManager {
+      api_key,
+      interval,
+      tracker: Arc::new(Mutex::new(TradeOfferStateTracker::new())),
+      time_historical_cutoff: None,
+      sender,
+    };
+
+    (manager, receiver)
+  }
+
+  // Shared with the running poll loop, so it stays reachable after start() consumes the manager
+  pub fn tracker(&self) -> Arc<Mutex<TradeOfferStateTracker>> {
+    Arc::clone(&self.tracker)
+  }
+
+  pub fn start(self) -> JoinHandle<()> {
+    tokio::spawn(self.run())
+  }
+
+  pub async fn run(mut self) {
+    let mut interval = tokio::time::interval(self.interval);
+
+    loop {
+      interval.tick().await;
+
+      if !self.poll().await {
+        break;
+      }
+    }
+  }
+
+  // Returns false once every receiver has been dropped
+  pub async fn poll(&mut self) -> bool {
+    let poll_started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+    let mut options = GetTradeOffersOptions::new();
+    options.time_historical_cutoff = Some(self.time_historical_cutoff.unwrap_or(poll_started));
+
+    // Every page is fetched before diffing so a failed poll leaves the tracker untouched
+    let mut polled: Vec<Offer> = Vec::new();
+    loop {
+      let page = match offers::get_trade_offers(&self.api_key, &options).await {
+        Ok(page) => page,
+        Err(e) => return self.emit(TradeOfferEvent::PollFailed(e)).await,
+      };
+
+      polled.extend(page.sent.into_iter().chain(page.received));
+
+      if !offers::next_page(&mut options, page.next_cursor) {
+        break;
+      }
+    }
+
+    let mut events: Vec<TradeOfferEvent> = Vec::new();
+    for offer in polled {
+      events.extend(self.diff(offer));
+    }
+
+    for event in events {
+      if !self.emit(event).await {
+        return false;
+      }
+    }
+
+    self.time_historical_cutoff = Some(poll_started.saturating_sub(CUTOFF_MARGIN_SECS));
+
+    true
+  }
+
+  fn diff(&mut self, offer: Offer) -> Vec<TradeOfferEvent> {
+    let mut events: Vec<TradeOfferEvent> = Vec::new();
+
+    let change = match self.tracker.lock().unwrap().record(&offer.tradeofferid, offer.state) {
+      Some(change) => change,
+      None => return events,
+    };
+
+    match change.from {
+      None => {
+        if !offer.is_our_offer && offer.state == TradeOfferState::Active {
+          events.push(TradeOfferEvent::NewOffer(offer.to_owned()));
+        }
+      },
+      Some(old_state) => {
+        if offer.is_our_offer {
+          events.push(TradeOfferEvent::SentOfferChanged { offer: offer.to_owned(), old_state });
+        } else {
+          events.push(TradeOfferEvent::ReceivedOfferChanged { offer: offer.to_owned(), old_state });
+        }
+      }
+    }
+
+    if offer.state == TradeOfferState::CreatedNeedsConfirmation {
+      events.push(TradeOfferEvent::NeedsConfirmation(offer.to_owned()));
+    }
+
+    if change.from.is_some() && offer.state == TradeOfferState::Accepted && !offer.items_to_receive.is_empty() {
+      events.push(TradeOfferEvent::ItemsReceived(offer));
+    }
+
+    events
+  }
+
+  async fn emit(&self, event: TradeOfferEvent) -> bool {
+    self.sender.send(event).await.is_ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::{description, offer, offer_item};
+
+  fn manager() -> TradeOfferManager {
+    TradeOfferManager::new(String::new(), Duration::from_secs(30)).0
+  }
+
+  fn with_state(mut offer: Offer, state: TradeOfferState, is_our_offer: bool) -> Offer {
+    offer.state = state;
+    offer.is_our_offer = is_our_offer;
+    offer
+  }
+
+  fn incoming() -> Offer {
+    offer(Vec::new(), vec![offer_item("1", Some(description("1", "AK-47 | Redline (Field-Tested)", &[])))])
+  }
+
+  #[test]
+  fn new_offer_only_for_incoming_active() {
+    let mut manager = manager();
+
+    assert_eq!(manager.diff(incoming()), vec![TradeOfferEvent::NewOffer(incoming())]);
+    // Seen before with the same state
+    assert_eq!(manager.diff(incoming()), Vec::new());
+
+    let mut sent = with_state(incoming(), TradeOfferState::Active, true);
+    sent.tradeofferid = "2".to_string();
+    assert_eq!(manager.diff(sent), Vec::new());
+
+    let mut declined = with_state(incoming(), TradeOfferState::Declined, false);
+    declined.tradeofferid = "3".to_string();
+    assert_eq!(manager.diff(declined), Vec::new());
+  }
+
+  #[test]
+  fn changes_are_split_by_direction() {
+    let mut manager = manager();
+
+    manager.diff(incoming());
+    let declined = with_state(incoming(), TradeOfferState::Declined, false);
+    assert_eq!(manager.diff(declined.to_owned()), vec![TradeOfferEvent::ReceivedOfferChanged { offer: declined, old_state: TradeOfferState::Active }]);
+
+    let mut sent = with_state(incoming(), TradeOfferState::Active, true);
+    sent.tradeofferid = "2".to_string();
+    manager.diff(sent.to_owned());
+    let canceled = with_state(sent, TradeOfferState::Canceled, true);
+    assert_eq!(manager.diff(canceled.to_owned()), vec![TradeOfferEvent::SentOfferChanged { offer: canceled, old_state: TradeOfferState::Active }]);
+  }
+
+  #[test]
+  fn needs_confirmation() {
+    let mut manager = manager();
+
+    let created = with_state(incoming(), TradeOfferState::CreatedNeedsConfirmation, true);
+    assert_eq!(manager.diff(created.to_owned()), vec![TradeOfferEvent::NeedsConfirmation(created)]);
+
+    let active = with_state(incoming(), TradeOfferState::Active, true);
+    assert_eq!(manager.diff(active.to_owned()), vec![TradeOfferEvent::SentOfferChanged { offer: active, old_state: TradeOfferState::CreatedNeedsConfirmation }]);
+  }
+
+  #[test]
+  fn items_received_only_on_observed_acceptance() {
+    let mut manager = manager();
+
+    // Already accepted the first time we see it, nothing was observed
+    let mut old = with_state(incoming(), TradeOfferState::Accepted, false);
+    old.tradeofferid = "9".to_string();
+    assert_eq!(manager.diff(old), Vec::new());
+
+    manager.diff(incoming());
+    let accepted = with_state(incoming(), TradeOfferState::Accepted, false);
+    assert_eq!(manager.diff(accepted.to_owned()), vec![
+      TradeOfferEvent::ReceivedOfferChanged { offer: accepted.to_owned(), old_state: TradeOfferState::Active },
+      TradeOfferEvent::ItemsReceived(accepted),
+    ]);
+
+    // A gift we sent has nothing to receive
+    let mut gift = with_state(offer(vec![offer_item("5", None)], Vec::new()), TradeOfferState::Active, true);
+    gift.tradeofferid = "5".to_string();
+    manager.diff(gift.to_owned());
+    let given = with_state(gift, TradeOfferState::Accepted, true);
+    assert_eq!(manager.diff(given.to_owned()), vec![TradeOfferEvent::SentOfferChanged { offer: given, old_state: TradeOfferState::Active }]);
+  }
+}
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use serde::{Deserialize, Serialize};
+
+pub const STEAM_FEE_PERCENT: f64 = 0.05;
+pub const STEAM_FEE_MINIMUM: u64 = 1;
+pub const STEAM_FEE_BASE: u64 = 0;
+pub const DEFAULT_PUBLISHER_FEE_PERCENT: f64 = 0.10;
+
+// Apps whose publisher fee isn't the default, keyed by appid
+static PUBLISHER_FEES: LazyLock<Mutex<HashMap<i64, f64>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FeeSchedule {
+  pub steam_fee_percent: f64,
+  pub steam_fee_minimum: u64,
+  pub steam_fee_base: u64,
+  pub publisher_fee_percent: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fees {
+  pub steam_fee: u64,
+  pub publisher_fee: u64,
+  pub fees: u64,
+  // What the buyer pays
+  pub amount: u64,
+  // What the seller receives
+  pub received: u64,
+}
+
+impl FeeSchedule {
+  pub fn new(publisher_fee_percent: f64) -> FeeSchedule {
+    FeeSchedule {
+      steam_fee_percent: STEAM_FEE_PERCENT,
+      steam_fee_minimum: STEAM_FEE_MINIMUM,
+      steam_fee_base: STEAM_FEE_BASE,
+      publisher_fee_percent,
+    }
+  }
+
+  // Steam's default publisher fee unless the app has one from set_publisher_fee
+  pub fn for_appid(appid: i64) -> FeeSchedule {
+    let publisher_fee_percent = PUBLISHER_FEES.lock().unwrap().get(&appid).copied();
+    FeeSchedule::new(publisher_fee_percent.unwrap_or(DEFAULT_PUBLISHER_FEE_PERCENT))
+  }
+
+  pub fn set_publisher_fee(appid: i64, publisher_fee_percent: f64) {
+    PUBLISHER_FEES.lock().unwrap().insert(appid, publisher_fee_percent);
+  }
+
+  // Mirrors CalculateAmountToSendForDesiredReceivedAmount from Steam's market javascript
+  pub fn fees_for_received(&self, received: u64) -> Fees {
+    let steam_fee = ((received as f64 * self.steam_fee_percent).max(self.steam_fee_minimum as f64) + self.steam_fee_base as f64).floor() as u64;
+    let publisher_fee = match self.publisher_fee_percent > 0.0 {
+      true => (received as f64 * self.publisher_fee_percent).max(1.0).floor() as u64,
+      false => 0,
+    };
+
+    Fees { steam_fee, publisher_fee, fees: steam_fee + publisher_fee, amount: received + steam_fee + publisher_fee, received }
+  }
+
+  // Mirrors CalculateFeeAmount, finding what the seller receives for a buyer price.
+  // Below 2 cents the javascript goes negative, here it returns the fees for receiving 0
+  pub fn fees_for_amount(&self, amount: u64) -> Fees {
+    let divisor = self.steam_fee_percent + self.publisher_fee_percent + 1.0;
+    let estimate = (amount as f64 - self.steam_fee_base as f64) / divisor;
+    let mut estimate = estimate.max(0.0) as u64;
+
+    let mut ever_undershot = false;
+    let mut fees = self.fees_for_received(estimate);
+    let mut iterations = 0;
+
+    while fees.amount != amount && iterations < 10 {
+      if fees.amount > amount {
+        if ever_undershot {
+          fees = self.fees_for_received(estimate.saturating_sub(1));
+          fees.steam_fee += amount.saturating_sub(fees.amount);
+          fees.fees += amount.saturating_sub(fees.amount);
+          fees.amount = amount;
+          break;
+        }
+        estimate = estimate.saturating_sub(1);
+      } else {
+        ever_undershot = true;
+        estimate += 1;
+      }
+
+      fees = self.fees_for_received(estimate);
+      iterations += 1;
+    }
+
+    fees
+  }
+}
+
+pub fn fees_for_received(appid: i64, received: u64) -> Fees {
+  FeeSchedule::for_appid(appid).fees_for_received(received)
+}
+
+pub fn fees_for_amount(appid: i64, amount: u64) -> Fees {
+  FeeSchedule::for_appid(appid).fees_for_amount(amount)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // (amount, received, steam fee, publisher fee) from CalculateFeeAmount in Steam's
+  // economy_common.js, run with the default 5% Steam and 10% publisher fees
+  const STEAM_FEE_AMOUNTS: [(u64, u64, u64, u64); 34] = [
+    (3, 1, 1, 1),
+    (4, 2, 1, 1),
+    (5, 3, 1, 1),
+    (10, 8, 1, 1),
+    (21, 19, 1, 1),
+    (22, 19, 2, 1),
+    (23, 20, 1, 2),
+    (33, 29, 2, 2),
+    (44, 39, 2, 3),
+    (45, 39, 3, 3),
+    (50, 44, 2, 4),
+    (56, 49, 3, 4),
+    (67, 59, 3, 5),
+    (68, 59, 4, 5),
+    (99, 87, 4, 8),
+    (100, 88, 4, 8),
+    (115, 100, 5, 10),
+    (116, 101, 5, 10),
+    (117, 102, 5, 10),
+    (200, 175, 8, 17),
+    (250, 219, 10, 21),
+    (333, 290, 14, 29),
+    (999, 869, 44, 86),
+    (1000, 870, 43, 87),
+    (1001, 871, 43, 87),
+    (1234, 1074, 53, 107),
+    (2500, 2175, 108, 217),
+    (9999, 8696, 434, 869),
+    (10000, 8697, 434, 869),
+    (12345, 10736, 536, 1073),
+    (99999, 86957, 4347, 8695),
+    (100000, 86958, 4347, 8695),
+    (123457, 107355, 5367, 10735),
+    (1000000, 869566, 43478, 86956),
+  ];
+
+  // The same with other publisher fees, (publisher fee percent, amount, received, steam fee, publisher fee)
+  const STEAM_FEE_AMOUNTS_OTHER_PUBLISHER_FEES: [(f64, u64, u64, u64, u64); 18] = [
+    (0.0, 2, 1, 1, 0),
+    (0.0, 3, 2, 1, 0),
+    (0.0, 22, 21, 1, 0),
+    (0.0, 100, 96, 4, 0),
+    (0.0, 1000, 953, 47, 0),
+    (0.0, 12345, 11758, 587, 0),
+    (0.05, 2, 0, 1, 1),
+    (0.05, 3, 1, 1, 1),
+    (0.05, 22, 20, 1, 1),
+    (0.05, 100, 92, 4, 4),
+    (0.05, 1000, 910, 45, 45),
+    (0.05, 12345, 11223, 561, 561),
+    (0.15, 2, 0, 1, 1),
+    (0.15, 3, 1, 1, 1),
+    (0.15, 22, 19, 1, 2),
+    (0.15, 100, 84, 4, 12),
+    (0.15, 1000, 834, 41, 125),
+    (0.15, 12345, 10288, 514, 1543),
+  ];
+
+  #[test]
+  fn minimum_fees() {
+    let schedule = FeeSchedule::for_appid(730);
+
+    // 1 cent each for Steam and the publisher, so 3 cents is the cheapest listing
+    assert_eq!(schedule.fees_for_received(1), Fees { steam_fee: 1, publisher_fee: 1, fees: 2, amount: 3, received: 1 });
+    assert_eq!(schedule.fees_for_amount(3), Fees { steam_fee: 1, publisher_fee: 1, fees: 2, amount: 3, received: 1 });
+    assert_eq!(schedule.fees_for_amount(2), Fees { steam_fee: 1, publisher_fee: 1, fees: 2, amount: 2, received: 0 });
+
+    // Steam would hand back -1 here
+    assert_eq!(schedule.fees_for_amount(1), schedule.fees_for_received(0));
+    assert_eq!(schedule.fees_for_amount(0), schedule.fees_for_received(0));
+
+    let no_publisher = FeeSchedule::new(0.0);
+    assert_eq!(no_publisher.fees_for_received(1), Fees { steam_fee: 1, publisher_fee: 0, fees: 1, amount: 2, received: 1 });
+    assert_eq!(no_publisher.fees_for_amount(2), Fees { steam_fee: 1, publisher_fee: 0, fees: 1, amount: 2, received: 1 });
+  }
+
+  #[test]
+  fn known_prices() {
+    let schedule = FeeSchedule::for_appid(730);
+
+    assert_eq!(schedule.fees_for_received(100), Fees { steam_fee: 5, publisher_fee: 10, fees: 15, amount: 115, received: 100 });
+    assert_eq!(schedule.fees_for_amount(115).received, 100);
+    assert_eq!(schedule.fees_for_amount(116).received, 101);
+    assert_eq!(schedule.fees_for_amount(1000).received, 870);
+    assert_eq!(schedule.fees_for_received(870).amount, 1000);
+  }
+
+  #[test]
+  fn ever_undershot_gives_the_extra_cent_to_steam() {
+    let schedule = FeeSchedule::for_appid(730);
+
+    // Receiving 19 costs 21 and receiving 20 costs 23, so nothing lands on 22
+    assert_eq!(schedule.fees_for_received(19).amount, 21);
+    assert_eq!(schedule.fees_for_received(20).amount, 23);
+    assert_eq!(schedule.fees_for_amount(22), Fees { steam_fee: 2, publisher_fee: 1, fees: 3, amount: 22, received: 19 });
+  }
+
+  #[test]
+  fn matches_steam_fee_amounts() {
+    let schedule = FeeSchedule::for_appid(730);
+
+    for (amount, received, steam_fee, publisher_fee) in STEAM_FEE_AMOUNTS {
+      assert_eq!(schedule.fees_for_amount(amount), Fees { steam_fee, publisher_fee, fees: steam_fee + publisher_fee, amount, received }, "amount {}", amount);
+    }
+  }
+
+  #[test]
+  fn matches_steam_fee_amounts_for_other_publisher_fees() {
+    for (publisher_fee_percent, amount, received, steam_fee, publisher_fee) in STEAM_FEE_AMOUNTS_OTHER_PUBLISHER_FEES {
+      let schedule = FeeSchedule::new(publisher_fee_percent);
+      assert_eq!(schedule.fees_for_amount(amount), Fees { steam_fee, publisher_fee, fees: steam_fee + publisher_fee, amount, received }, "{} at {}", amount, publisher_fee_percent);
+    }
+  }
+
+  #[test]
+  fn publisher_fee_per_app() {
+    assert_eq!(FeeSchedule::for_appid(570), FeeSchedule::new(DEFAULT_PUBLISHER_FEE_PERCENT));
+
+    // Made up appid so other tests keep the default
+    FeeSchedule::set_publisher_fee(1_000_001, 0.15);
+    assert_eq!(FeeSchedule::for_appid(1_000_001).publisher_fee_percent, 0.15);
+    assert_eq!(fees_for_amount(1_000_001, 1000), FeeSchedule::new(0.15).fees_for_amount(1000));
+    assert_eq!(fees_for_amount(730, 1000).received, 870);
+  }
+
+  #[test]
+  fn every_cent_splits_and_round_trips() {
+    let schedule = FeeSchedule::for_appid(730);
+
+    for amount in 0..=1_000_000 {
+      let fees = schedule.fees_for_amount(amount);
+      if fees.received > 0 {
+        assert_eq!(fees.received + fees.fees, amount, "split for {}", amount);
+      }
+    }
+
+    for received in 0..=100_000 {
+      let fees = schedule.fees_for_received(received);
+      assert_eq!(schedule.fees_for_amount(fees.amount), fees);
+    }
+  }
+}
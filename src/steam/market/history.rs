@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use reqwest::{Client, StatusCode};
+use crate::steam::Inventory::UnauthorizedResponse;
+
+const DAY_SECS: u64 = 86400;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PriceHistory {
+  pub appid: i64,
+  pub market_hash_name: String,
+  pub price_prefix: String,
+  pub price_suffix: String,
+  pub points: Vec<PricePoint>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PricePoint {
+  pub time: u64,
+  pub median_cents: u64,
+  pub volume: u64,
+}
+
+// One per day, median_cents is the median of that day's points and average_cents their volume weighted mean
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DailyPrice {
+  pub time: u64,
+  pub median_cents: u64,
+  pub average_cents: u64,
+  pub volume: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PriceStatistics {
+  pub median_7d: Option<u64>,
+  pub median_30d: Option<u64>,
+  // Plain mean of the daily medians from the last 7 days, see moving_average for the series
+  pub mean_7d: Option<f64>,
+  pub volatility_30d: Option<f64>,
+  pub daily_volume_7d: f64,
+  pub daily_volume_30d: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PriceHistoryError {
+  Network(String),
+  Steam(String),
+  Unauthorized(UnauthorizedResponse),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct PriceHistoryQuery<'a> {
+  appid: i64,
+  market_hash_name: &'a str,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct PriceHistoryResponse {
+  success: bool,
+  #[serde(default)]
+  price_prefix: String,
+  #[serde(default)]
+  price_suffix: String,
+  #[serde(default)]
+  prices: Vec<(String, f64, Value)>,
+}
+
+impl PriceHistory {
+  pub async fn fetch(cookie: &String, appid: i64, market_hash_name: &str) -> Result<PriceHistory, PriceHistoryError> {
+    let query = PriceHistoryQuery { appid, market_hash_name };
+
+    let client = Client::new();
+    let res = match client.get("https://steamcommunity.com/market/pricehistory/").query(&query).header("Cookie", cookie).header("Accept", "application/json").send().await {
+      Ok(res) => res,
+      Err(e) => return Err(PriceHistoryError::Network(e.to_string()))
+    };
+
+    let status = res.status().to_owned();
+    let text = match res.text().await {
+      Ok(text) => text,
+      Err(e) => return Err(PriceHistoryError::Network(e.to_string()))
+    };
+
+    match status {
+      StatusCode::OK => (),
+      _ => return Err(PriceHistoryError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }))
+    }
+
+    match PriceHistory::parse(appid, market_hash_name, &text) {
+      Some(history) => Ok(history),
+      None => Err(PriceHistoryError::Steam(text))
+    }
+  }
+
+  pub fn parse(appid: i64, market_hash_name: &str, text: &str) -> Option<PriceHistory> {
+    let response = match serde_json::from_str::<PriceHistoryResponse>(text) {
+      Ok(res) if res.success => res,
+      _ => return None
+    };
+
+    let points = response.prices.iter().filter_map(|(date, median, volume)| {
+      let volume = match volume {
+        Value::String(v) => v.parse::<u64>().ok()?,
+        Value::Number(v) => v.as_u64()?,
+        _ => return None,
+      };
+
+      Some(PricePoint { time: parse_history_date(date)?, median_cents: (median * 100.0).round() as u64, volume })
+    }).collect();
+
+    Some(PriceHistory {
+      appid,
+      market_hash_name: market_hash_name.to_string(),
+      price_prefix: response.price_prefix,
+      price_suffix: response.price_suffix,
+      points,
+    })
+  }
+
+  // Recent history is hourly and older history daily, this evens it out to one point per day
+  pub fn daily(&self) -> Vec<DailyPrice> {
+    let mut days: BTreeMap<u64, Vec<&PricePoint>> = BTreeMap::new();
+
+    for point in &self.points {
+      days.entry(point.time / DAY_SECS * DAY_SECS).or_default().push(point);
+    }
+
+    days.into_iter().map(|(time, points)| {
+      let volume = points.iter().map(|p| p.volume).sum::<u64>();
+      let median_cents = median(points.iter().map(|p| p.median_cents).collect()).unwrap_or(0);
+
+      let average_cents = match volume {
+        0 => points.iter().map(|p| p.median_cents).sum::<u64>() / points.len() as u64,
+        v => (points.iter().map(|p| p.median_cents as f64 * p.volume as f64).sum::<f64>() / v as f64).round() as u64,
+      };
+
+      DailyPrice { time, median_cents, average_cents, volume }
+    }).collect()
+  }
+
+  pub fn since(&self, days: u64) -> Vec<DailyPrice> {
+    self.since_at(days, now())
+  }
+
+  pub fn since_at(&self, days: u64, now: u64) -> Vec<DailyPrice> {
+    let cutoff = now.saturating_sub(days * DAY_SECS);
+    self.daily().into_iter().filter(|p| p.time >= cutoff).collect()
+  }
+
+  // Simple moving average of daily medians, one value per full window
+  pub fn moving_average(&self, window: usize) -> Vec<f64> {
+    let daily = self.daily();
+    if window == 0 || daily.len() < window {
+      return Vec::new();
+    }
+
+    daily.windows(window)
+      .map(|w| w.iter().map(|p| p.median_cents as f64).sum::<f64>() / window as f64)
+      .collect()
+  }
+
+  pub fn median(&self, days: u64) -> Option<u64> {
+    self.median_at(days, now())
+  }
+
+  // Median of the daily medians in the window, so hourly days don't outweigh daily ones
+  pub fn median_at(&self, days: u64, now: u64) -> Option<u64> {
+    median(self.since_at(days, now).iter().map(|p| p.median_cents).collect())
+  }
+
+  // Standard deviation of daily log returns
+  pub fn volatility(&self, days: u64) -> Option<f64> {
+    self.volatility_at(days, now())
+  }
+
+  pub fn volatility_at(&self, days: u64, now: u64) -> Option<f64> {
+    volatility(&self.since_at(days, now))
+  }
+
+  pub fn daily_volume(&self, days: u64) -> f64 {
+    self.daily_volume_at(days, now())
+  }
+
+  pub fn daily_volume_at(&self, days: u64, now: u64) -> f64 {
+    match days {
+      0 => 0.0,
+      d => self.since_at(days, now).iter().map(|p| p.volume).sum::<u64>() as f64 / d as f64,
+    }
+  }
+
+  pub fn statistics(&self) -> PriceStatistics {
+    self.statistics_at(now())
+  }
+
+  pub fn statistics_at(&self, now: u64) -> PriceStatistics {
+    let recent = self.since_at(7, now);
+    let mean_7d = match recent.len() {
+      0 => None,
+      n => Some(recent.iter().map(|p| p.median_cents as f64).sum::<f64>() / n as f64),
+    };
+
+    PriceStatistics {
+      median_7d: self.median_at(7, now),
+      median_30d: self.median_at(30, now),
+      mean_7d,
+      volatility_30d: self.volatility_at(30, now),
+      daily_volume_7d: self.daily_volume_at(7, now),
+      daily_volume_30d: self.daily_volume_at(30, now),
+    }
+  }
+}
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+fn median(mut values: Vec<u64>) -> Option<u64> {
+  if values.is_empty() {
+    return None;
+  }
+
+  values.sort_unstable();
+  let mid = values.len() / 2;
+
+  match values.len() % 2 {
+    0 => Some((values[mid - 1] + values[mid]) / 2),
+    _ => Some(values[mid]),
+  }
+}
+
+fn volatility(points: &[DailyPrice]) -> Option<f64> {
+  let returns = points.windows(2)
+    .filter(|w| w[0].median_cents > 0 && w[1].median_cents > 0)
+    .map(|w| (w[1].median_cents as f64 / w[0].median_cents as f64).ln())
+    .collect::<Vec<f64>>();
+
+  if returns.len() < 2 {
+    return None;
+  }
+
+  let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+  let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+
+  Some(variance.sqrt())
+}
+
+// Dates look like "Nov 27 2013 01: +0"
+fn parse_history_date(date: &str) -> Option<u64> {
+  let mut parts = date.split_whitespace();
+
+  let month = match parts.next()? {
+    "Jan" => 1, "Feb" => 2, "Mar" => 3, "Apr" => 4, "May" => 5, "Jun" => 6,
+    "Jul" => 7, "Aug" => 8, "Sep" => 9, "Oct" => 10, "Nov" => 11, "Dec" => 12,
+    _ => return None,
+  };
+  let day = parts.next()?.parse::<u64>().ok()?;
+  let year = parts.next()?.parse::<i64>().ok()?;
+  let hour = parts.next()?.trim_end_matches(':').parse::<u64>().ok()?;
+
+  let days = days_from_civil(year, month, day)?;
+
+  Some(days * DAY_SECS + hour * 3600)
+}
+
+fn days_from_civil(year: i64, month: i64, day: u64) -> Option<u64> {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let yoe = year - era * 400;
+  let mp = (month + 9) % 12;
+  let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  let days = era * 146097 + doe - 719468;
+
+  u64::try_from(days).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Nov 27 2013 00:00 UTC
+  const NOV_27_2013: u64 = 1385510400;
+
+  fn point(day: u64, hour: u64, median_cents: u64, volume: u64) -> PricePoint {
+    PricePoint { time: NOV_27_2013 + day * DAY_SECS + hour * 3600, median_cents, volume }
+  }
+
+  fn history(points: Vec<PricePoint>) -> PriceHistory {
+    PriceHistory { appid: 730, market_hash_name: "Mann Co. Supply Crate Key".to_string(), price_prefix: "$".to_string(), price_suffix: String::new(), points }
+  }
+
+  #[test]
+  fn parses_history_dates() {
+    assert_eq!(parse_history_date("Nov 27 2013 01: +0"), Some(NOV_27_2013 + 3600));
+    assert_eq!(parse_history_date("Jan 01 1970 00: +0"), Some(0));
+    assert_eq!(parse_history_date("Feb 29 2024 23: +0"), Some(1709247600));
+    assert_eq!(parse_history_date("Mar 01 2024 00: +0"), Some(1709251200));
+    assert_eq!(parse_history_date("Foo 01 2024 00: +0"), None);
+    assert_eq!(parse_history_date("Nov 27 2013"), None);
+    assert_eq!(parse_history_date("Dec 31 1969 00: +0"), None);
+  }
+
+  #[test]
+  fn parses_price_history() {
+    let text = r#"{"success":true,"price_prefix":"$","price_suffix":"","prices":[["Nov 27 2013 01: +0",1.234,"12"],["Nov 27 2013 02: +0",2.5,3],["bad date",1.0,"1"]]}"#;
+    let history = PriceHistory::parse(730, "Mann Co. Supply Crate Key", text).unwrap();
+
+    assert_eq!(history.points, vec![point(0, 1, 123, 12), point(0, 2, 250, 3)]);
+    assert_eq!(history.price_prefix, "$");
+
+    assert_eq!(PriceHistory::parse(730, "x", r#"{"success":false}"#), None);
+    assert_eq!(PriceHistory::parse(730, "x", "[]"), None);
+  }
+
+  #[test]
+  fn median_of_values() {
+    assert_eq!(median(vec![]), None);
+    assert_eq!(median(vec![5]), Some(5));
+    assert_eq!(median(vec![9, 1, 5]), Some(5));
+    assert_eq!(median(vec![4, 1, 3, 2]), Some(2));
+  }
+
+  #[test]
+  fn daily_buckets_points() {
+    let history = history(vec![
+      point(0, 1, 100, 1),
+      point(0, 2, 200, 3),
+      point(0, 3, 900, 0),
+      point(1, 0, 300, 0),
+      point(1, 1, 500, 0),
+    ]);
+
+    assert_eq!(history.daily(), vec![
+      DailyPrice { time: NOV_27_2013, median_cents: 200, average_cents: 175, volume: 4 },
+      DailyPrice { time: NOV_27_2013 + DAY_SECS, median_cents: 400, average_cents: 400, volume: 0 },
+    ]);
+  }
+
+  #[test]
+  fn windows_are_relative_to_now() {
+    let history = history((0..30).map(|d| point(d, 0, 100 + d, 10)).collect());
+    let now = NOV_27_2013 + 29 * DAY_SECS + 12 * 3600;
+
+    let week = history.since_at(7, now);
+    assert_eq!(week.len(), 7);
+    assert_eq!(week[0].median_cents, 123);
+
+    assert_eq!(history.median_at(7, now), Some(126));
+    assert_eq!(history.median_at(30, now), Some(114));
+    assert_eq!(history.daily_volume_at(7, now), 10.0);
+    assert_eq!(history.daily_volume_at(0, now), 0.0);
+  }
+
+  #[test]
+  fn median_weighs_each_day_once() {
+    // A day of hourly points followed by two daily ones
+    let mut points = (0..24).map(|h| point(0, h, 100, 1)).collect::<Vec<PricePoint>>();
+    points.push(point(1, 0, 200, 1));
+    points.push(point(2, 0, 300, 1));
+    let history = history(points);
+
+    assert_eq!(history.median_at(30, NOV_27_2013 + 3 * DAY_SECS), Some(200));
+  }
+
+  #[test]
+  fn moving_average_series() {
+    let history = history((0..5).map(|d| point(d, 0, (d + 1) * 100, 1)).collect());
+
+    assert_eq!(history.moving_average(3), vec![200.0, 300.0, 400.0]);
+    assert_eq!(history.moving_average(5), vec![300.0]);
+    assert!(history.moving_average(6).is_empty());
+    assert!(history.moving_average(0).is_empty());
+  }
+
+  #[test]
+  fn volatility_of_log_returns() {
+    let flat = history((0..10).map(|d| point(d, 0, 100, 1)).collect());
+    assert_eq!(flat.volatility_at(30, NOV_27_2013 + 10 * DAY_SECS), Some(0.0));
+
+    // Alternating +10% and -10% moves
+    let swings = history((0..10).map(|d| point(d, 0, if d % 2 == 0 { 100 } else { 110 }, 1)).collect());
+    let volatility = swings.volatility_at(30, NOV_27_2013 + 10 * DAY_SECS).unwrap();
+    let returns = (0..9).map(|d| if d % 2 == 0 { (1.1f64).ln() } else { -(1.1f64).ln() }).collect::<Vec<f64>>();
+    let mean = returns.iter().sum::<f64>() / 9.0;
+    let expected = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 8.0).sqrt();
+    assert!((volatility - expected).abs() < 1e-9, "{} != {}", volatility, expected);
+
+    let short = history(vec![point(0, 0, 100, 1), point(1, 0, 110, 1)]);
+    assert_eq!(short.volatility_at(30, NOV_27_2013 + 2 * DAY_SECS), None);
+  }
+
+  #[test]
+  fn statistics_at_fixed_time() {
+    let history = history((0..30).map(|d| point(d, 0, 100 + d, 10)).collect());
+    let statistics = history.statistics_at(NOV_27_2013 + 29 * DAY_SECS + 12 * 3600);
+
+    assert_eq!(statistics.median_7d, Some(126));
+    assert_eq!(statistics.mean_7d, Some(126.0));
+    assert_eq!(statistics.daily_volume_30d, 10.0);
+    assert!(statistics.volatility_30d.unwrap() > 0.0);
+  }
+}
@@ -0,0 +1,442 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use reqwest::{Client, StatusCode};
+use crate::steam::account::Account;
+use crate::steam::Inventory::UnauthorizedResponse;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MyListings {
+  pub active: Vec<Listing>,
+  pub on_hold: Vec<Listing>,
+  pub to_confirm: Vec<Listing>,
+  pub buy_orders: Vec<BuyOrder>,
+  pub total_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Listing {
+  pub listingid: String,
+  pub time_created: u64,
+  pub appid: i64,
+  pub contextid: String,
+  pub assetid: String,
+  pub amount: String,
+  pub market_hash_name: Option<String>,
+  // What the seller receives, in the listing currency
+  pub price: u64,
+  pub fee: u64,
+  pub currencyid: i64,
+  pub awaiting_confirmation: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BuyOrder {
+  pub buy_orderid: String,
+  pub appid: i64,
+  pub market_hash_name: String,
+  pub wallet_currency: i64,
+  pub price: u64,
+  pub quantity: u64,
+  pub quantity_remaining: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BuyOrderStatus {
+  pub active: bool,
+  pub purchased: u64,
+  pub quantity: u64,
+  pub quantity_remaining: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum MarketError {
+  Network(String),
+  Steam(String),
+  Unauthorized(UnauthorizedResponse),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct MyListingsQuery {
+  start: u64,
+  count: u64,
+  norender: u8,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct MyListingsResponse {
+  success: bool,
+  #[serde(default)]
+  total_count: u64,
+  #[serde(default)]
+  listings: Vec<RawListing>,
+  #[serde(default)]
+  listings_on_hold: Vec<RawListing>,
+  #[serde(default)]
+  listings_to_confirm: Vec<RawListing>,
+  #[serde(default)]
+  buy_orders: Vec<RawBuyOrder>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct RawListing {
+  listingid: String,
+  time_created: u64,
+  asset: RawListingAsset,
+  #[serde(default)]
+  price: u64,
+  #[serde(default)]
+  fee: u64,
+  #[serde(default)]
+  currencyid: i64,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct RawListingAsset {
+  appid: i64,
+  contextid: String,
+  id: String,
+  amount: String,
+  market_hash_name: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct RawBuyOrder {
+  buy_orderid: String,
+  appid: i64,
+  hash_name: String,
+  wallet_currency: i64,
+  price: String,
+  quantity: String,
+  quantity_remaining: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct SessionForm {
+  sessionid: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct CreateBuyOrderForm {
+  sessionid: String,
+  currency: i64,
+  appid: i64,
+  market_hash_name: String,
+  price_total: u64,
+  quantity: u64,
+  billing_state: String,
+  save_my_address: u8,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct BuyOrderForm {
+  sessionid: String,
+  buy_orderid: String,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct MarketResponse {
+  success: Value,
+  message: Option<String>,
+  buy_orderid: Option<String>,
+  active: Option<i32>,
+  purchased: Option<u64>,
+  quantity: Option<String>,
+  quantity_remaining: Option<String>,
+}
+
+impl MyListings {
+  pub async fn fetch(account: &Account) -> Result<MyListings, MarketError> {
+    let mut listings = MyListings { active: Vec::new(), on_hold: Vec::new(), to_confirm: Vec::new(), buy_orders: Vec::new(), total_count: 0 };
+    let count = 100;
+    let mut start = 0;
+
+    loop {
+      let query = MyListingsQuery { start, count, norender: 1 };
+
+      let client = Client::new();
+      let res = match client.get("https://steamcommunity.com/market/mylistings/").query(&query).header("Cookie", &account.cookie).header("Accept", "application/json").send().await {
+        Ok(res) => res,
+        Err(e) => return Err(MarketError::Network(e.to_string()))
+      };
+
+      let text = check_status(res).await?;
+      let fetched = listings.add_page(&text, start == 0)?;
+
+      start += count;
+      if fetched == 0 || start >= listings.total_count {
+        break;
+      }
+    }
+
+    Ok(listings)
+  }
+
+  // Returns how many active listings the page held
+  fn add_page(&mut self, text: &str, first: bool) -> Result<u64, MarketError> {
+    let response = match serde_json::from_str::<MyListingsResponse>(text) {
+      Ok(res) if res.success => res,
+      _ => return Err(MarketError::Steam(text.to_string()))
+    };
+
+    // Listings are paged, the rest only come with the first page
+    if first {
+      self.on_hold = response.listings_on_hold.into_iter().map(|l| l.into_listing(false)).collect();
+      self.to_confirm = response.listings_to_confirm.into_iter().map(|l| l.into_listing(true)).collect();
+      self.buy_orders = response.buy_orders.into_iter().map(|b| b.into_buy_order()).collect();
+    }
+
+    let fetched = response.listings.len() as u64;
+    self.total_count = response.total_count;
+    self.active.extend(response.listings.into_iter().map(|l| l.into_listing(false)));
+
+    Ok(fetched)
+  }
+}
+
+pub async fn remove_listing(account: &Account, listingid: &String) -> Result<(), MarketError> {
+  let url = format!("https://steamcommunity.com/market/removelisting/{}", listingid);
+  let form_data = SessionForm { sessionid: crate::steam::create_session_id() };
+
+  market_post(account, &url, "https://steamcommunity.com/market/", &form_data.sessionid, &form_data).await?;
+
+  Ok(())
+}
+
+// Price is per item, in cents of the wallet currency
+pub async fn create_buy_order(account: &Account, appid: i64, market_hash_name: &String, currency: i64, price: u64, quantity: u64) -> Result<String, MarketError> {
+  let form_data = CreateBuyOrderForm {
+    sessionid: crate::steam::create_session_id(),
+    currency,
+    appid,
+    market_hash_name: market_hash_name.to_owned(),
+    price_total: price * quantity,
+    quantity,
+    billing_state: "".to_string(),
+    save_my_address: 0,
+  };
+
+  let listing_url = listing_url(appid, market_hash_name);
+
+  let response = market_post(account, "https://steamcommunity.com/market/createbuyorder/", &listing_url, &form_data.sessionid, &form_data).await?;
+
+  match response.buy_orderid {
+    Some(buy_orderid) => Ok(buy_orderid),
+    None => Err(MarketError::Steam("Missing buy order id".to_string()))
+  }
+}
+
+// Names can contain ?, # and /, pushing them as a segment percent-encodes those
+fn listing_url(appid: i64, market_hash_name: &str) -> String {
+  let mut url = reqwest::Url::parse("https://steamcommunity.com/market/listings").unwrap();
+  url.path_segments_mut().unwrap().push(&appid.to_string()).push(market_hash_name);
+  url.to_string()
+}
+
+pub async fn cancel_buy_order(account: &Account, buy_orderid: &String) -> Result<(), MarketError> {
+  let form_data = BuyOrderForm { sessionid: crate::steam::create_session_id(), buy_orderid: buy_orderid.to_owned() };
+
+  market_post(account, "https://steamcommunity.com/market/cancelbuyorder/", "https://steamcommunity.com/market/", &form_data.sessionid, &form_data).await?;
+
+  Ok(())
+}
+
+pub async fn get_buy_order_status(account: &Account, buy_orderid: &String) -> Result<BuyOrderStatus, MarketError> {
+  let form_data = BuyOrderForm { sessionid: crate::steam::create_session_id(), buy_orderid: buy_orderid.to_owned() };
+  let cookie = format!("{}sessionid={};", account.cookie, &form_data.sessionid);
+
+  let client = Client::new();
+  let res = match client.get("https://steamcommunity.com/market/getbuyorderstatus/").query(&form_data).header("Cookie", cookie).header("Accept", "application/json").send().await {
+    Ok(res) => res,
+    Err(e) => return Err(MarketError::Network(e.to_string()))
+  };
+
+  let text = check_status(res).await?;
+  let response = parse_market_response(text)?;
+
+  Ok(BuyOrderStatus {
+    active: response.active.unwrap_or(0) != 0,
+    purchased: response.purchased.unwrap_or(0),
+    quantity: response.quantity.and_then(|q| q.parse::<u64>().ok()).unwrap_or(0),
+    quantity_remaining: response.quantity_remaining.and_then(|q| q.parse::<u64>().ok()).unwrap_or(0),
+  })
+}
+
+async fn market_post<T: Serialize>(account: &Account, url: &str, referer: &str, session_id: &String, form: &T) -> Result<MarketResponse, MarketError> {
+  let cookie = format!("{}sessionid={};", account.cookie, session_id);
+
+  let client = Client::new();
+  let res = match client.post(url).header("Referer", referer).header("Cookie", cookie).form(form).send().await {
+    Ok(res) => res,
+    Err(e) => return Err(MarketError::Network(e.to_string()))
+  };
+
+  let text = check_status(res).await?;
+
+  // removelisting answers with an empty array on success
+  if text.trim() == "[]" {
+    return Ok(MarketResponse { success: Value::Bool(true), message: None, buy_orderid: None, active: None, purchased: None, quantity: None, quantity_remaining: None });
+  }
+
+  parse_market_response(text)
+}
+
+async fn check_status(res: reqwest::Response) -> Result<String, MarketError> {
+  let status = res.status().to_owned();
+  let text = match res.text().await {
+    Ok(text) => text,
+    Err(e) => return Err(MarketError::Network(e.to_string()))
+  };
+
+  match status {
+    StatusCode::OK => Ok(text),
+    _ => Err(MarketError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }))
+  }
+}
+
+fn parse_market_response(text: String) -> Result<MarketResponse, MarketError> {
+  let response = match serde_json::from_str::<MarketResponse>(&text) {
+    Ok(response) => response,
+    Err(_) => return Err(MarketError::Steam(text))
+  };
+
+  // success is a bool on some endpoints and an eresult on others
+  let success = match &response.success {
+    Value::Bool(b) => *b,
+    Value::Number(n) => n.as_i64() == Some(1),
+    _ => false,
+  };
+
+  match success {
+    true => Ok(response),
+    false => Err(MarketError::Steam(response.message.to_owned().unwrap_or(text)))
+  }
+}
+
+impl RawListing {
+  fn into_listing(self, awaiting_confirmation: bool) -> Listing {
+    Listing {
+      listingid: self.listingid,
+      time_created: self.time_created,
+      appid: self.asset.appid,
+      contextid: self.asset.contextid,
+      assetid: self.asset.id,
+      amount: self.asset.amount,
+      market_hash_name: self.asset.market_hash_name,
+      price: self.price,
+      fee: self.fee,
+      currencyid: self.currencyid,
+      awaiting_confirmation,
+    }
+  }
+}
+
+impl RawBuyOrder {
+  fn into_buy_order(self) -> BuyOrder {
+    BuyOrder {
+      buy_orderid: self.buy_orderid,
+      appid: self.appid,
+      market_hash_name: self.hash_name,
+      wallet_currency: self.wallet_currency,
+      price: self.price.parse::<u64>().unwrap_or(0),
+      quantity: self.quantity.parse::<u64>().unwrap_or(0),
+      quantity_remaining: self.quantity_remaining.parse::<u64>().unwrap_or(0),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn listing_url_keeps_name_in_one_segment() {
+    assert_eq!(listing_url(730, "AK-47 | Redline (Field-Tested)"), "https://steamcommunity.com/market/listings/730/AK-47%20|%20Redline%20(Field-Tested)");
+    assert_eq!(listing_url(440, "Why? #1/2"), "https://steamcommunity.com/market/listings/440/Why%3F%20%231%2F2");
+    assert_eq!(listing_url(730, "★ Karambit"), "https://steamcommunity.com/market/listings/730/%E2%98%85%20Karambit");
+  }
+
+  fn listing(listingid: &str, assetid: &str, awaiting_confirmation: bool) -> Listing {
+    Listing {
+      listingid: listingid.to_string(),
+      time_created: 1700000000,
+      appid: 730,
+      contextid: "2".to_string(),
+      assetid: assetid.to_string(),
+      amount: "1".to_string(),
+      market_hash_name: Some("AK-47 | Redline (Field-Tested)".to_string()),
+      price: 870,
+      fee: 130,
+      currencyid: 2001,
+      awaiting_confirmation,
+    }
+  }
+
+  fn raw_listing(listingid: &str, assetid: &str) -> String {
+    format!(r#"{{"listingid":"{}","time_created":1700000000,"asset":{{"currency":0,"appid":730,"contextid":"2","id":"{}","amount":"1","market_hash_name":"AK-47 | Redline (Field-Tested)","status":2}},"price":870,"fee":130,"currencyid":2001,"steam_fee":43,"publisher_fee":87,"status":2}}"#, listingid, assetid)
+  }
+
+  fn empty() -> MyListings {
+    MyListings { active: Vec::new(), on_hold: Vec::new(), to_confirm: Vec::new(), buy_orders: Vec::new(), total_count: 0 }
+  }
+
+  #[test]
+  fn parses_every_listing_kind() {
+    let text = format!(
+      r#"{{"success":true,"pagesize":100,"total_count":2,"start":0,"num_active_listings":2,"listings":[{},{}],"listings_on_hold":[{}],"listings_to_confirm":[{}],"buy_orders":[{{"appid":730,"hash_name":"Operation Breakout Weapon Case","wallet_currency":1,"price":"125","quantity":"10","quantity_remaining":"4","buy_orderid":"5555","description":{{}}}}]}}"#,
+      raw_listing("1", "10"), raw_listing("2", "11"), raw_listing("3", "12"), raw_listing("4", "13")
+    );
+
+    let mut listings = empty();
+    assert_eq!(listings.add_page(&text, true), Ok(2));
+
+    assert_eq!(listings.active, vec![listing("1", "10", false), listing("2", "11", false)]);
+    assert_eq!(listings.on_hold, vec![listing("3", "12", false)]);
+    assert_eq!(listings.to_confirm, vec![listing("4", "13", true)]);
+    assert_eq!(listings.buy_orders, vec![BuyOrder {
+      buy_orderid: "5555".to_string(),
+      appid: 730,
+      market_hash_name: "Operation Breakout Weapon Case".to_string(),
+      wallet_currency: 1,
+      price: 125,
+      quantity: 10,
+      quantity_remaining: 4,
+    }]);
+    assert_eq!(listings.total_count, 2);
+  }
+
+  #[test]
+  fn later_pages_only_add_active_listings() {
+    let first = format!(r#"{{"success":true,"total_count":3,"listings":[{}],"listings_on_hold":[{}],"buy_orders":[]}}"#, raw_listing("1", "10"), raw_listing("3", "12"));
+    let second = format!(r#"{{"success":true,"total_count":3,"listings":[{},{}],"listings_on_hold":[],"buy_orders":[]}}"#, raw_listing("2", "11"), raw_listing("5", "14"));
+
+    let mut listings = empty();
+    assert_eq!(listings.add_page(&first, true), Ok(1));
+    assert_eq!(listings.add_page(&second, false), Ok(2));
+
+    assert_eq!(listings.active.iter().map(|l| l.listingid.as_str()).collect::<Vec<&str>>(), vec!["1", "2", "5"]);
+    assert_eq!(listings.on_hold, vec![listing("3", "12", false)]);
+    assert!(listings.to_confirm.is_empty());
+  }
+
+  #[test]
+  fn failed_listings_page() {
+    let mut listings = empty();
+
+    assert_eq!(listings.add_page(r#"{"success":false}"#, true), Err(MarketError::Steam(r#"{"success":false}"#.to_string())));
+    assert_eq!(listings.add_page("<html></html>", true), Err(MarketError::Steam("<html></html>".to_string())));
+    assert_eq!(listings, empty());
+  }
+
+  #[test]
+  fn success_as_bool_or_eresult() {
+    let response = parse_market_response(r#"{"success":1,"buy_orderid":"5555"}"#.to_string()).unwrap();
+    assert_eq!(response.buy_orderid, Some("5555".to_string()));
+
+    let response = parse_market_response(r#"{"success":true,"active":1,"purchased":6,"quantity":"10","quantity_remaining":"4"}"#.to_string()).unwrap();
+    assert_eq!((response.active, response.purchased, response.quantity.as_deref(), response.quantity_remaining.as_deref()), (Some(1), Some(6), Some("10"), Some("4")));
+
+    assert_eq!(parse_market_response(r#"{"success":false,"message":"You already have an order"}"#.to_string()).err(), Some(MarketError::Steam("You already have an order".to_string())));
+    assert_eq!(parse_market_response(r#"{"success":29}"#.to_string()).err(), Some(MarketError::Steam(r#"{"success":29}"#.to_string())));
+    assert_eq!(parse_market_response("null".to_string()).err(), Some(MarketError::Steam("null".to_string())));
+  }
+}
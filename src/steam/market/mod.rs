@@ -0,0 +1,4 @@
+pub mod fees;
+pub mod history;
+pub mod listings;
+pub mod sell;
@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use reqwest::{Client, StatusCode};
+use crate::steam::account::Account;
+use crate::steam::Inventory::{Asset, AssetDescription, UnauthorizedResponse};
+use super::fees::{self, Fees};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum SellPrice {
+  BuyerPays(u64),
+  SellerReceives(u64),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SellResult {
+  pub fees: Fees,
+  pub requires_confirmation: bool,
+  pub needs_mobile_confirmation: bool,
+  pub needs_email_confirmation: bool,
+  pub email_domain: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SellError {
+  NotMarketable,
+  DescriptionMismatch,
+  PriceTooLow,
+  Network(String),
+  Steam(String),
+  Unauthorized(UnauthorizedResponse),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct SellItemForm {
+  sessionid: String,
+  appid: i64,
+  contextid: String,
+  assetid: String,
+  amount: u64,
+  price: u64,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct SellItemResponse {
+  success: bool,
+  message: Option<String>,
+  #[serde(default)]
+  requires_confirmation: i32,
+  #[serde(default)]
+  needs_mobile_confirmation: bool,
+  #[serde(default)]
+  needs_email_confirmation: bool,
+  email_domain: Option<String>,
+}
+
+impl SellPrice {
+  pub fn fees(&self, appid: i64) -> Fees {
+    match self {
+      SellPrice::BuyerPays(amount) => fees::fees_for_amount(appid, *amount),
+      SellPrice::SellerReceives(received) => fees::fees_for_received(appid, *received),
+    }
+  }
+}
+
+pub async fn sell_item(account: &Account, asset: &Asset, description: &AssetDescription, amount: u64, price: SellPrice) -> Result<SellResult, SellError> {
+  let (form_data, fees) = sell_item_form(asset, description, amount, price)?;
+
+  let referer = format!("https://steamcommunity.com/profiles/{}/inventory/", account.steam_id);
+  let cookie = format!("{}sessionid={};", account.cookie, &form_data.sessionid);
+
+  let client = Client::new();
+  let res = match client.post("https://steamcommunity.com/market/sellitem/").header("Referer", referer).header("Cookie", cookie).form(&form_data).send().await {
+    Ok(res) => res,
+    Err(e) => return Err(SellError::Network(e.to_string()))
+  };
+
+  let status = res.status().to_owned();
+  let text = match res.text().await {
+    Ok(text) => text,
+    Err(e) => return Err(SellError::Network(e.to_string()))
+  };
+
+  let response = match serde_json::from_str::<SellItemResponse>(&text) {
+    Ok(response) => response,
+    Err(_) => return Err(SellError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }))
+  };
+
+  if !response.success {
+    return Err(SellError::Steam(response.message.unwrap_or(text)));
+  }
+
+  if status != StatusCode::OK {
+    return Err(SellError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }));
+  }
+
+  Ok(SellResult {
+    fees,
+    requires_confirmation: response.requires_confirmation != 0,
+    needs_mobile_confirmation: response.needs_mobile_confirmation,
+    needs_email_confirmation: response.needs_email_confirmation,
+    email_domain: response.email_domain.filter(|d| !d.is_empty()),
+  })
+}
+
+// Everything sell_item checks before it touches the network
+fn sell_item_form(asset: &Asset, description: &AssetDescription, amount: u64, price: SellPrice) -> Result<(SellItemForm, Fees), SellError> {
+  if description.marketable != 1 {
+    return Err(SellError::NotMarketable);
+  }
+
+  if description.classid != asset.classid || description.instanceid != asset.instanceid {
+    return Err(SellError::DescriptionMismatch);
+  }
+
+  let fees = price.fees(asset.appid);
+  if fees.received < 1 {
+    return Err(SellError::PriceTooLow);
+  }
+
+  let form_data = SellItemForm {
+    sessionid: crate::steam::create_session_id(),
+    appid: asset.appid,
+    contextid: asset.contextid.to_owned(),
+    assetid: asset.assetid.to_owned(),
+    amount,
+    // Steam expects what the seller receives
+    price: fees.received,
+  };
+
+  Ok((form_data, fees))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::description;
+
+  fn asset(classid: &str) -> Asset {
+    Asset { appid: 730, contextid: "2".to_string(), assetid: "10".to_string(), classid: classid.to_string(), instanceid: "0".to_string(), amount: "1".to_string() }
+  }
+
+  #[test]
+  fn rejects_before_sending() {
+    let mut not_marketable = description("1", "AK-47 | Redline (Field-Tested)", &[]);
+    not_marketable.marketable = 0;
+    assert_eq!(sell_item_form(&asset("1"), &not_marketable, 1, SellPrice::BuyerPays(115)).err(), Some(SellError::NotMarketable));
+
+    let description = description("1", "AK-47 | Redline (Field-Tested)", &[]);
+    assert_eq!(sell_item_form(&asset("2"), &description, 1, SellPrice::BuyerPays(115)).err(), Some(SellError::DescriptionMismatch));
+
+    let mut other_instance = asset("1");
+    other_instance.instanceid = "5".to_string();
+    assert_eq!(sell_item_form(&other_instance, &description, 1, SellPrice::BuyerPays(115)).err(), Some(SellError::DescriptionMismatch));
+
+    // 2 cents is all fees
+    assert_eq!(sell_item_form(&asset("1"), &description, 1, SellPrice::BuyerPays(2)).err(), Some(SellError::PriceTooLow));
+    assert_eq!(sell_item_form(&asset("1"), &description, 1, SellPrice::SellerReceives(0)).err(), Some(SellError::PriceTooLow));
+  }
+
+  #[test]
+  fn sends_what_the_seller_receives() {
+    let description = description("1", "AK-47 | Redline (Field-Tested)", &[]);
+
+    let (form, fees) = sell_item_form(&asset("1"), &description, 1, SellPrice::BuyerPays(115)).unwrap();
+    assert_eq!(form.price, 100);
+    assert_eq!(fees.amount, 115);
+
+    let (form, fees) = sell_item_form(&asset("1"), &description, 1, SellPrice::SellerReceives(100)).unwrap();
+    assert_eq!(form.price, 100);
+    assert_eq!(fees.amount, 115);
+
+    let (form, _) = sell_item_form(&asset("1"), &description, 3, SellPrice::BuyerPays(1000)).unwrap();
+    assert_eq!((form.price, form.amount, form.assetid.as_str(), form.contextid.as_str()), (870, 3, "10", "2"));
+  }
+}
@@ -3,6 +3,14 @@ use rand::{rngs::StdRng, RngCore, SeedableRng};
 pub mod Inventory;
 pub mod account;
 pub mod Trade;
+pub mod offers;
+pub mod offer_state;
+pub mod manager;
+pub mod trade_history;
+pub mod trade_url;
+pub mod steam_id;
+pub mod bulk_trade;
+pub mod market;
 
 pub fn convert_parterid_to_steamid(partner_id: &String) -> u64 {
   let id = partner_id.parse::<u64>().unwrap();
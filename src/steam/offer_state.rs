@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TradeOfferState {
+  Invalid,
+  Active,
+  Accepted,
+  Countered,
+  Expired,
+  Canceled,
+  Declined,
+  InvalidItems,
+  CreatedNeedsConfirmation,
+  CanceledBySecondFactor,
+  InEscrow,
+  Unknown(i32),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StateChange {
+  pub from: Option<TradeOfferState>,
+  pub to: TradeOfferState,
+  pub time: u64,
+  pub legal: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeOfferStateTracker {
+  history: HashMap<String, Vec<StateChange>>,
+  counters: HashMap<String, String>,
+  countered_from: HashMap<String, String>,
+}
+
+impl TradeOfferState {
+  pub fn from_i32(state: i32) -> TradeOfferState {
+    match state {
+      1 => TradeOfferState::Invalid,
+      2 => TradeOfferState::Active,
+      3 => TradeOfferState::Accepted,
+      4 => TradeOfferState::Countered,
+      5 => TradeOfferState::Expired,
+      6 => TradeOfferState::Canceled,
+      7 => TradeOfferState::Declined,
+      8 => TradeOfferState::InvalidItems,
+      9 => TradeOfferState::CreatedNeedsConfirmation,
+      10 => TradeOfferState::CanceledBySecondFactor,
+      11 => TradeOfferState::InEscrow,
+      s => TradeOfferState::Unknown(s),
+    }
+  }
+
+  pub fn to_i32(self) -> i32 {
+    match self {
+      TradeOfferState::Invalid => 1,
+      TradeOfferState::Active => 2,
+      TradeOfferState::Accepted => 3,
+      TradeOfferState::Countered => 4,
+      TradeOfferState::Expired => 5,
+      TradeOfferState::Canceled => 6,
+      TradeOfferState::Declined => 7,
+      TradeOfferState::InvalidItems => 8,
+      TradeOfferState::CreatedNeedsConfirmation => 9,
+      TradeOfferState::CanceledBySecondFactor => 10,
+      TradeOfferState::InEscrow => 11,
+      TradeOfferState::Unknown(s) => s,
+    }
+  }
+
+  pub fn is_terminal(self) -> bool {
+    matches!(self,
+      TradeOfferState::Accepted |
+      TradeOfferState::Countered |
+      TradeOfferState::Expired |
+      TradeOfferState::Canceled |
+      TradeOfferState::Declined |
+      TradeOfferState::CanceledBySecondFactor
+    )
+  }
+
+  pub fn can_transition_to(self, next: TradeOfferState) -> bool {
+    if self == next {
+      return true;
+    }
+
+    match self {
+      TradeOfferState::CreatedNeedsConfirmation => matches!(next,
+        TradeOfferState::Active |
+        TradeOfferState::CanceledBySecondFactor |
+        TradeOfferState::Canceled |
+        TradeOfferState::Expired
+      ),
+      TradeOfferState::Active => matches!(next,
+        TradeOfferState::Accepted |
+        TradeOfferState::Countered |
+        TradeOfferState::Expired |
+        TradeOfferState::Canceled |
+        TradeOfferState::Declined |
+        TradeOfferState::InvalidItems |
+        TradeOfferState::InEscrow
+      ),
+      // Items can come back into the inventory and re-validate the offer
+      TradeOfferState::InvalidItems => matches!(next,
+        TradeOfferState::Active |
+        TradeOfferState::Expired |
+        TradeOfferState::Canceled |
+        TradeOfferState::Declined
+      ),
+      // Escrow either completes or is rolled back
+      TradeOfferState::InEscrow => matches!(next,
+        TradeOfferState::Accepted |
+        TradeOfferState::Canceled |
+        TradeOfferState::InvalidItems
+      ),
+      TradeOfferState::Unknown(_) => true,
+      _ => false,
+    }
+  }
+}
+
+impl TradeOfferStateTracker {
+  pub fn new() -> TradeOfferStateTracker {
+    TradeOfferStateTracker { history: HashMap::new(), counters: HashMap::new(), countered_from: HashMap::new() }
+  }
+
+  pub fn record(&mut self, tradeofferid: &String, state: TradeOfferState) -> Option<StateChange> {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    self.record_at(tradeofferid, state, time)
+  }
+
+  pub fn record_at(&mut self, tradeofferid: &String, state: TradeOfferState, time: u64) -> Option<StateChange> {
+    let history = self.history.entry(tradeofferid.to_owned()).or_default();
+    let from = history.last().map(|c| c.to);
+
+    if from == Some(state) {
+      return None;
+    }
+
+    let legal = match from {
+      Some(from) => from.can_transition_to(state),
+      None => true,
+    };
+
+    let change = StateChange { from, to: state, time, legal };
+    history.push(change.to_owned());
+
+    Some(change)
+  }
+
+  pub fn current(&self, tradeofferid: &String) -> Option<TradeOfferState> {
+    self.history.get(tradeofferid).and_then(|h| h.last()).map(|c| c.to)
+  }
+
+  pub fn history(&self, tradeofferid: &String) -> Option<&Vec<StateChange>> {
+    self.history.get(tradeofferid)
+  }
+
+  pub fn is_completed(&self, tradeofferid: &String) -> bool {
+    self.current(tradeofferid) == Some(TradeOfferState::Accepted)
+  }
+
+  pub fn is_in_escrow(&self, tradeofferid: &String) -> bool {
+    self.current(tradeofferid) == Some(TradeOfferState::InEscrow)
+  }
+
+  pub fn record_counter(&mut self, original_tradeofferid: &String, counter_tradeofferid: &String) {
+    self.counters.insert(original_tradeofferid.to_owned(), counter_tradeofferid.to_owned());
+    self.countered_from.insert(counter_tradeofferid.to_owned(), original_tradeofferid.to_owned());
+  }
+
+  pub fn countered_by(&self, original_tradeofferid: &String) -> Option<&String> {
+    self.counters.get(original_tradeofferid)
+  }
+
+  pub fn counter_of(&self, counter_tradeofferid: &String) -> Option<&String> {
+    self.countered_from.get(counter_tradeofferid)
+  }
+
+  pub fn forget(&mut self, tradeofferid: &String) -> Option<Vec<StateChange>> {
+    if let Some(counter) = self.counters.remove(tradeofferid) {
+      self.countered_from.remove(&counter);
+    }
+    if let Some(original) = self.countered_from.remove(tradeofferid) {
+      self.counters.remove(&original);
+    }
+    self.history.remove(tradeofferid)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ALL: [TradeOfferState; 11] = [
+    TradeOfferState::Invalid,
+    TradeOfferState::Active,
+    TradeOfferState::Accepted,
+    TradeOfferState::Countered,
+    TradeOfferState::Expired,
+    TradeOfferState::Canceled,
+    TradeOfferState::Declined,
+    TradeOfferState::InvalidItems,
+    TradeOfferState::CreatedNeedsConfirmation,
+    TradeOfferState::CanceledBySecondFactor,
+    TradeOfferState::InEscrow,
+  ];
+
+  fn legal_targets(state: TradeOfferState) -> Vec<TradeOfferState> {
+    use TradeOfferState::*;
+
+    match state {
+      CreatedNeedsConfirmation => vec![Active, CanceledBySecondFactor, Canceled, Expired],
+      Active => vec![Accepted, Countered, Expired, Canceled, Declined, InvalidItems, InEscrow],
+      InvalidItems => vec![Active, Expired, Canceled, Declined],
+      InEscrow => vec![Accepted, Canceled, InvalidItems],
+      _ => vec![],
+    }
+  }
+
+  #[test]
+  fn i32_round_trip() {
+    for state in ALL {
+      assert_eq!(TradeOfferState::from_i32(state.to_i32()), state);
+    }
+    assert_eq!(TradeOfferState::from_i32(42), TradeOfferState::Unknown(42));
+    assert_eq!(TradeOfferState::Unknown(42).to_i32(), 42);
+  }
+
+  #[test]
+  fn transition_table() {
+    for from in ALL {
+      let legal = legal_targets(from);
+
+      for to in ALL {
+        let expected = from == to || legal.contains(&to);
+        assert_eq!(from.can_transition_to(to), expected, "{:?} -> {:?}", from, to);
+      }
+    }
+  }
+
+  #[test]
+  fn terminal_states_only_stay_put() {
+    for from in ALL.into_iter().filter(|s| s.is_terminal()) {
+      for to in ALL.into_iter().filter(|s| *s != from) {
+        assert!(!from.can_transition_to(to), "{:?} -> {:?}", from, to);
+      }
+    }
+  }
+
+  #[test]
+  fn unknown_can_go_anywhere() {
+    for to in ALL {
+      assert!(TradeOfferState::Unknown(99).can_transition_to(to));
+    }
+  }
+
+  #[test]
+  fn tracker_records_changes() {
+    let mut tracker = TradeOfferStateTracker::new();
+    let id = "1".to_string();
+
+    let first = tracker.record_at(&id, TradeOfferState::Active, 10).unwrap();
+    assert_eq!(first, StateChange { from: None, to: TradeOfferState::Active, time: 10, legal: true });
+
+    assert_eq!(tracker.record_at(&id, TradeOfferState::Active, 20), None);
+
+    let accepted = tracker.record_at(&id, TradeOfferState::Accepted, 30).unwrap();
+    assert!(accepted.legal);
+    assert!(tracker.is_completed(&id));
+
+    let illegal = tracker.record_at(&id, TradeOfferState::Active, 40).unwrap();
+    assert!(!illegal.legal);
+    assert_eq!(tracker.history(&id).unwrap().len(), 3);
+    assert_eq!(tracker.current(&id), Some(TradeOfferState::Active));
+  }
+
+  #[test]
+  fn tracker_links_counters_both_ways() {
+    let mut tracker = TradeOfferStateTracker::new();
+    let original = "1".to_string();
+    let counter = "2".to_string();
+
+    tracker.record_counter(&original, &counter);
+    assert_eq!(tracker.countered_by(&original), Some(&counter));
+    assert_eq!(tracker.counter_of(&counter), Some(&original));
+
+    tracker.forget(&counter);
+    assert_eq!(tracker.countered_by(&original), None);
+    assert_eq!(tracker.counter_of(&counter), None);
+  }
+}
@@ -0,0 +1,679 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use reqwest::{Client, StatusCode};
+use regex::Regex;
+use super::Inventory::{AssetDescription, Description, Action, Tag, UnauthorizedResponse};
+use super::offer_state::TradeOfferState;
+use super::Trade::OfferAsset;
+use super::steam_id::SteamId;
+
+// Steam appends the eresult to its error strings, e.g. "There was an error sending your trade offer. (26)"
+static ERESULT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\((\d+)\)\s*$").unwrap());
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Offer {
+  pub tradeofferid: String,
+  pub tradeid: Option<String>,
+  pub partner: SteamId,
+  pub accountid_other: u32,
+  pub message: String,
+  pub state: TradeOfferState,
+  pub is_our_offer: bool,
+  pub items_to_give: Vec<OfferItem>,
+  pub items_to_receive: Vec<OfferItem>,
+  pub time_created: u64,
+  pub time_updated: u64,
+  pub expiration_time: u64,
+  pub escrow_end_date: u64,
+  pub from_real_time_trade: bool,
+  pub confirmation_method: i32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct OfferItem {
+  pub appid: i64,
+  pub contextid: String,
+  pub assetid: String,
+  pub classid: String,
+  pub instanceid: String,
+  pub amount: String,
+  pub missing: bool,
+  pub description: Option<AssetDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeOffers {
+  pub sent: Vec<Offer>,
+  pub received: Vec<Offer>,
+  pub next_cursor: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeOfferAccepted {
+  pub tradeid: Option<String>,
+  pub needs_mobile_confirmation: Option<bool>,
+  pub needs_email_confirmation: Option<bool>,
+  pub email_domain: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeOfferActionResult {
+  pub tradeofferid: String,
+  pub result: Result<(), OfferError>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum OfferError {
+  Unauthorized(UnauthorizedResponse),
+  Steam(SteamError),
+  // Steam answered with a body we could not make sense of
+  Parse(String),
+  // The request never got a response, usually a timeout, and is safe to retry
+  Network(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SteamError {
+  pub eresult: Option<i32>,
+  pub message: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct AcceptOfferForm {
+  sessionid: String,
+  serverid: String,
+  tradeofferid: String,
+  partner: String,
+  captcha: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct OfferSessionForm {
+  sessionid: String,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct SteamErrorResponse {
+  #[serde(rename = "strError")]
+  str_error: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct GetTradeOffersOptions {
+  pub get_sent_offers: bool,
+  pub get_received_offers: bool,
+  pub get_descriptions: bool,
+  pub active_only: bool,
+  pub historical_only: bool,
+  pub time_historical_cutoff: Option<u64>,
+  pub language: String,
+  // next_cursor of the previous page
+  pub cursor: Option<u64>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct GetTradeOfferQuery<'a> {
+  tradeofferid: &'a String,
+  get_descriptions: bool,
+  language: &'a str,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct EconResponse<T> {
+  pub(crate) response: T,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EconTradeOffers {
+  #[serde(default)]
+  trade_offers_sent: Vec<EconOffer>,
+  #[serde(default)]
+  trade_offers_received: Vec<EconOffer>,
+  #[serde(default)]
+  descriptions: Vec<EconDescription>,
+  next_cursor: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EconTradeOffer {
+  offer: Option<EconOffer>,
+  #[serde(default)]
+  descriptions: Vec<EconDescription>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EconOffer {
+  tradeofferid: String,
+  tradeid: Option<String>,
+  accountid_other: u32,
+  #[serde(default)]
+  message: String,
+  expiration_time: u64,
+  trade_offer_state: i32,
+  #[serde(default)]
+  items_to_give: Vec<EconItem>,
+  #[serde(default)]
+  items_to_receive: Vec<EconItem>,
+  is_our_offer: bool,
+  time_created: u64,
+  time_updated: u64,
+  #[serde(default)]
+  from_real_time_trade: bool,
+  #[serde(default)]
+  escrow_end_date: u64,
+  #[serde(default)]
+  confirmation_method: i32,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EconItem {
+  appid: i64,
+  contextid: String,
+  assetid: String,
+  classid: String,
+  instanceid: String,
+  amount: String,
+  #[serde(default)]
+  missing: bool,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct EconDescription {
+  appid: i64,
+  classid: String,
+  instanceid: String,
+  #[serde(default)]
+  currency: bool,
+  #[serde(default)]
+  background_color: String,
+  #[serde(default)]
+  icon_url: String,
+  icon_url_large: Option<String>,
+  #[serde(default)]
+  descriptions: Vec<Description>,
+  #[serde(default)]
+  tradable: bool,
+  actions: Option<Vec<Action>>,
+  #[serde(default)]
+  name: String,
+  name_color: Option<String>,
+  #[serde(rename = "type", default)]
+  _type: String,
+  #[serde(default)]
+  market_name: String,
+  #[serde(default)]
+  market_hash_name: String,
+  market_actions: Option<Vec<Action>>,
+  #[serde(default)]
+  commodity: bool,
+  #[serde(default)]
+  market_tradable_restriction: i64,
+  market_marketable_restriction: Option<i64>,
+  #[serde(default)]
+  marketable: bool,
+  #[serde(default)]
+  tags: Vec<EconTag>,
+  market_buy_country_restriction: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EconTag {
+  category: String,
+  internal_name: String,
+  #[serde(alias = "category_name")]
+  localized_category_name: String,
+  #[serde(alias = "name")]
+  localized_tag_name: String,
+  color: Option<String>,
+}
+
+impl GetTradeOffersOptions {
+  pub fn new() -> GetTradeOffersOptions {
+    GetTradeOffersOptions {
+      get_sent_offers: true,
+      get_received_offers: true,
+      get_descriptions: true,
+      active_only: true,
+      historical_only: false,
+      time_historical_cutoff: None,
+      language: "english".to_string(),
+      cursor: None,
+    }
+  }
+}
+
+pub async fn get_trade_offers(api_key: &String, options: &GetTradeOffersOptions) -> Result<TradeOffers, OfferError> {
+  let text = econ_get("https://api.steampowered.com/IEconService/GetTradeOffers/v1/", api_key, options).await?;
+
+  parse_trade_offers(&text)
+}
+
+fn parse_trade_offers(text: &str) -> Result<TradeOffers, OfferError> {
+  let response = match serde_json::from_str::<EconResponse<EconTradeOffers>>(text) {
+    Ok(res) => res.response,
+    Err(e) => return Err(OfferError::Parse(e.to_string()))
+  };
+
+  let descriptions = index_descriptions(response.descriptions);
+
+  Ok(TradeOffers {
+    sent: response.trade_offers_sent.into_iter().map(|o| o.into_offer(&descriptions)).collect(),
+    received: response.trade_offers_received.into_iter().map(|o| o.into_offer(&descriptions)).collect(),
+    next_cursor: response.next_cursor,
+  })
+}
+
+pub async fn get_trade_offer(api_key: &String, tradeofferid: &String, get_descriptions: bool) -> Result<Option<Offer>, OfferError> {
+  let query = GetTradeOfferQuery { tradeofferid, get_descriptions, language: "english" };
+
+  let text = econ_get("https://api.steampowered.com/IEconService/GetTradeOffer/v1/", api_key, &query).await?;
+
+  parse_trade_offer(&text)
+}
+
+fn parse_trade_offer(text: &str) -> Result<Option<Offer>, OfferError> {
+  let response = match serde_json::from_str::<EconResponse<EconTradeOffer>>(text) {
+    Ok(res) => res.response,
+    Err(e) => return Err(OfferError::Parse(e.to_string()))
+  };
+
+  let descriptions = index_descriptions(response.descriptions);
+
+  Ok(response.offer.map(|o| o.into_offer(&descriptions)))
+}
+
+impl Offer {
+  pub async fn accept(&self, cookie: &String) -> Result<TradeOfferAccepted, OfferError> {
+    accept_trade_offer(cookie, &self.tradeofferid, &self.partner).await
+  }
+
+  pub async fn decline(&self, cookie: &String) -> Result<(), OfferError> {
+    decline_trade_offer(cookie, &self.tradeofferid).await
+  }
+
+  pub async fn cancel(&self, cookie: &String) -> Result<(), OfferError> {
+    cancel_trade_offer(cookie, &self.tradeofferid).await
+  }
+}
+
+pub async fn accept_trade_offer(cookie: &String, tradeofferid: &String, partner: &SteamId) -> Result<TradeOfferAccepted, OfferError> {
+  let form_data = AcceptOfferForm {
+    sessionid: super::create_session_id(),
+    serverid: "1".to_string(),
+    tradeofferid: tradeofferid.to_owned(),
+    partner: partner.to_string(),
+    captcha: "".to_string(),
+  };
+
+  let url = format!("https://steamcommunity.com/tradeoffer/{}/accept", tradeofferid);
+  let referer = format!("https://steamcommunity.com/tradeoffer/{}/", tradeofferid);
+
+  let text = community_post(&url, &referer, cookie, &form_data.sessionid, &form_data).await?;
+
+  let accepted = match serde_json::from_str::<TradeOfferAccepted>(&text) {
+    Ok(accepted) => accepted,
+    Err(e) => return Err(OfferError::Parse(e.to_string()))
+  };
+
+  Ok(accepted)
+}
+
+pub async fn decline_trade_offer(cookie: &String, tradeofferid: &String) -> Result<(), OfferError> {
+  offer_action(cookie, tradeofferid, "decline").await
+}
+
+pub async fn cancel_trade_offer(cookie: &String, tradeofferid: &String) -> Result<(), OfferError> {
+  offer_action(cookie, tradeofferid, "cancel").await
+}
+
+pub async fn cancel_trade_offers(cookie: &String, tradeofferids: &Vec<String>) -> Vec<TradeOfferActionResult> {
+  let mut results: Vec<TradeOfferActionResult> = Vec::new();
+
+  for tradeofferid in tradeofferids {
+    let result = cancel_trade_offer(cookie, tradeofferid).await;
+    results.push(TradeOfferActionResult { tradeofferid: tradeofferid.to_owned(), result });
+  }
+
+  results
+}
+
+pub async fn cancel_stale_offers(api_key: &String, cookie: &String, older_than_minutes: u64) -> Result<Vec<TradeOfferActionResult>, OfferError> {
+  let mut options = GetTradeOffersOptions::new();
+  options.get_received_offers = false;
+  options.get_descriptions = false;
+
+  let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+  let cutoff = now.saturating_sub(older_than_minutes.saturating_mul(60));
+
+  let mut stale: Vec<String> = Vec::new();
+
+  loop {
+    let page = get_trade_offers(api_key, &options).await?;
+
+    stale.extend(page.sent.iter()
+      .filter(|o| o.state == TradeOfferState::Active && o.time_created < cutoff)
+      .map(|o| o.tradeofferid.to_owned()));
+
+    if !next_page(&mut options, page.next_cursor) {
+      break;
+    }
+  }
+
+  Ok(cancel_trade_offers(cookie, &stale).await)
+}
+
+// Moves options on to the next page, false once Steam reports no cursor, a zero cursor or the same one again
+pub(crate) fn next_page(options: &mut GetTradeOffersOptions, next_cursor: Option<u64>) -> bool {
+  match next_cursor {
+    Some(cursor) if cursor != 0 && Some(cursor) != options.cursor => {
+      options.cursor = Some(cursor);
+      true
+    },
+    _ => false,
+  }
+}
+
+async fn offer_action(cookie: &String, tradeofferid: &String, action: &str) -> Result<(), OfferError> {
+  let form_data = OfferSessionForm { sessionid: super::create_session_id() };
+
+  let url = format!("https://steamcommunity.com/tradeoffer/{}/{}", tradeofferid, action);
+  let referer = format!("https://steamcommunity.com/tradeoffer/{}/", tradeofferid);
+
+  community_post(&url, &referer, cookie, &form_data.sessionid, &form_data).await?;
+
+  Ok(())
+}
+
+pub(crate) async fn community_post<T: Serialize>(url: &str, referer: &str, cookie: &String, session_id: &String, form: &T) -> Result<String, OfferError> {
+  let client = Client::new();
+
+  let cookie = format!("{}sessionid={};", cookie, session_id);
+
+  let res = match client.post(url).header("Referer", referer).header("Cookie", cookie).form(form).send().await {
+    Ok(res) => res,
+    Err(e) => return Err(OfferError::Network(e.to_string()))
+  };
+
+  let status = res.status().to_owned();
+  let text = match res.text().await {
+    Ok(text) => text,
+    Err(e) => return Err(OfferError::Network(e.to_string()))
+  };
+
+  if let Ok(err) = serde_json::from_str::<SteamErrorResponse>(&text) {
+    return Err(OfferError::Steam(SteamError::parse(err.str_error)));
+  }
+
+  match status {
+    StatusCode::OK => Ok(text),
+    _ => Err(OfferError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }))
+  }
+}
+
+impl OfferItem {
+  pub fn to_offer_asset(&self) -> OfferAsset {
+    OfferAsset::new(self.appid.to_string(), self.contextid.to_owned(), self.amount.to_owned(), self.assetid.to_owned())
+  }
+}
+
+impl From<UnauthorizedResponse> for OfferError {
+  fn from(response: UnauthorizedResponse) -> OfferError {
+    OfferError::Unauthorized(response)
+  }
+}
+
+impl SteamError {
+  pub fn parse(message: String) -> SteamError {
+    let eresult = ERESULT.captures(&message).and_then(|c| c[1].parse::<i32>().ok());
+
+    SteamError { eresult, message }
+  }
+}
+
+pub(crate) async fn econ_get<T: Serialize>(url: &str, api_key: &String, query: &T) -> Result<String, OfferError> {
+  let client = Client::new();
+  let res = match client.get(url).query(&[("key", api_key)]).query(query).header("Accept", "application/json").send().await {
+    Ok(res) => res,
+    Err(e) => return Err(OfferError::Network(e.to_string()))
+  };
+
+  let status = res.status().to_owned();
+  let text = match res.text().await {
+    Ok(text) => text,
+    Err(e) => return Err(OfferError::Network(e.to_string()))
+  };
+
+  match status {
+    StatusCode::OK => Ok(text),
+    _ => Err(OfferError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }))
+  }
+}
+
+pub(crate) fn index_descriptions(descriptions: Vec<EconDescription>) -> HashMap<(i64, String, String), AssetDescription> {
+  descriptions.into_iter()
+    .map(|d| ((d.appid, d.classid.to_owned(), d.instanceid.to_owned()), d.into_asset_description()))
+    .collect()
+}
+
+impl EconOffer {
+  fn into_offer(self, descriptions: &HashMap<(i64, String, String), AssetDescription>) -> Offer {
+
+    Offer {
+      tradeofferid: self.tradeofferid,
+      tradeid: self.tradeid,
+      partner: SteamId::from_account_id(self.accountid_other),
+      accountid_other: self.accountid_other,
+      message: self.message,
+      state: TradeOfferState::from_i32(self.trade_offer_state),
+      is_our_offer: self.is_our_offer,
+      items_to_give: self.items_to_give.into_iter().map(|i| i.into_offer_item(descriptions)).collect(),
+      items_to_receive: self.items_to_receive.into_iter().map(|i| i.into_offer_item(descriptions)).collect(),
+      time_created: self.time_created,
+      time_updated: self.time_updated,
+      expiration_time: self.expiration_time,
+      escrow_end_date: self.escrow_end_date,
+      from_real_time_trade: self.from_real_time_trade,
+      confirmation_method: self.confirmation_method,
+    }
+  }
+}
+
+impl EconItem {
+  fn into_offer_item(self, descriptions: &HashMap<(i64, String, String), AssetDescription>) -> OfferItem {
+    let description = descriptions.get(&(self.appid, self.classid.to_owned(), self.instanceid.to_owned())).cloned();
+
+    OfferItem {
+      appid: self.appid,
+      contextid: self.contextid,
+      assetid: self.assetid,
+      classid: self.classid,
+      instanceid: self.instanceid,
+      amount: self.amount,
+      missing: self.missing,
+      description,
+    }
+  }
+}
+
+impl EconDescription {
+  fn into_asset_description(self) -> AssetDescription {
+    AssetDescription {
+      appid: self.appid,
+      classid: self.classid,
+      instanceid: self.instanceid,
+      currency: self.currency as i64,
+      background_color: self.background_color,
+      icon_url: self.icon_url,
+      icon_url_large: self.icon_url_large,
+      descriptions: self.descriptions,
+      tradable: self.tradable as i64,
+      actions: self.actions,
+      name: self.name,
+      name_color: self.name_color,
+      _type: self._type,
+      market_name: self.market_name,
+      market_hash_name: self.market_hash_name,
+      market_actions: self.market_actions,
+      commodity: self.commodity as i64,
+      market_tradable_restriction: self.market_tradable_restriction,
+      market_marketable_restriction: self.market_marketable_restriction,
+      marketable: self.marketable as i64,
+      tags: self.tags.into_iter().map(|t| Tag {
+        category: t.category,
+        internal_name: t.internal_name,
+        localized_category_name: t.localized_category_name,
+        localized_tag_name: t.localized_tag_name,
+        color: t.color,
+      }).collect(),
+      market_buy_country_restriction: self.market_buy_country_restriction,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TRADE_OFFERS: &str = r#"{
+    "response": {
+      "trade_offers_sent": [
+        {
+          "tradeofferid": "6000000001",
+          "accountid_other": 22202,
+          "message": "storage",
+          "expiration_time": 1700604800,
+          "trade_offer_state": 2,
+          "items_to_give": [
+            { "appid": 730, "contextid": "2", "assetid": "111", "classid": "310776668", "instanceid": "188530139", "amount": "1", "missing": false }
+          ],
+          "is_our_offer": true,
+          "time_created": 1699395200,
+          "time_updated": 1699395200,
+          "from_real_time_trade": false,
+          "escrow_end_date": 0,
+          "confirmation_method": 2
+        }
+      ],
+      "trade_offers_received": [
+        {
+          "tradeofferid": "6000000002",
+          "tradeid": "3000000009",
+          "accountid_other": 12345,
+          "expiration_time": 1700604800,
+          "trade_offer_state": 3,
+          "items_to_receive": [
+            { "appid": 730, "contextid": "2", "assetid": "222", "classid": "310776668", "instanceid": "188530139", "amount": "1" },
+            { "appid": 730, "contextid": "2", "assetid": "333", "classid": "999", "instanceid": "0", "amount": "1", "missing": true }
+          ],
+          "is_our_offer": false,
+          "time_created": 1699395100,
+          "time_updated": 1699395300
+        }
+      ],
+      "descriptions": [
+        {
+          "appid": 730,
+          "classid": "310776668",
+          "instanceid": "188530139",
+          "tradable": true,
+          "marketable": true,
+          "name": "AK-47 | Redline",
+          "type": "Classified Rifle",
+          "market_name": "AK-47 | Redline (Field-Tested)",
+          "market_hash_name": "AK-47 | Redline (Field-Tested)",
+          "tags": [
+            { "category": "Rarity", "internal_name": "Rarity_Legendary_Weapon", "localized_category_name": "Quality", "localized_tag_name": "Classified", "color": "d32ce6" }
+          ]
+        }
+      ],
+      "next_cursor": 100
+    }
+  }"#;
+
+  #[test]
+  fn parses_trade_offers() {
+    let offers = parse_trade_offers(TRADE_OFFERS).unwrap();
+
+    assert_eq!(offers.next_cursor, Some(100));
+    assert_eq!(offers.sent.len(), 1);
+    assert_eq!(offers.received.len(), 1);
+
+    let sent = &offers.sent[0];
+    assert_eq!(sent.tradeofferid, "6000000001");
+    assert_eq!(sent.tradeid, None);
+    assert_eq!(sent.partner, SteamId::from_account_id(22202));
+    assert_eq!(sent.state, TradeOfferState::Active);
+    assert!(sent.is_our_offer);
+    assert_eq!(sent.message, "storage");
+    assert_eq!(sent.confirmation_method, 2);
+    assert!(sent.items_to_receive.is_empty());
+
+    let received = &offers.received[0];
+    assert_eq!(received.state, TradeOfferState::Accepted);
+    assert_eq!(received.tradeid, Some("3000000009".to_string()));
+    assert_eq!(received.message, "");
+    assert_eq!(received.escrow_end_date, 0);
+    assert!(received.items_to_give.is_empty());
+    assert!(received.items_to_receive[1].missing);
+  }
+
+  #[test]
+  fn joins_descriptions_by_class_and_instance() {
+    let offers = parse_trade_offers(TRADE_OFFERS).unwrap();
+
+    let given = offers.sent[0].items_to_give[0].description.as_ref().unwrap();
+    assert_eq!(given.market_hash_name, "AK-47 | Redline (Field-Tested)");
+    assert_eq!(given.tradable, 1);
+    assert_eq!(given._type, "Classified Rifle");
+    assert_eq!(given.tags[0].localized_tag_name, "Classified");
+
+    let received = &offers.received[0].items_to_receive;
+    assert_eq!(received[0].description.as_ref().map(|d| d.classid.as_str()), Some("310776668"));
+    assert_eq!(received[1].description, None);
+  }
+
+  #[test]
+  fn parses_single_offer() {
+    let offer = parse_trade_offer(r#"{ "response": { "offer": {
+      "tradeofferid": "1", "accountid_other": 22202, "expiration_time": 0, "trade_offer_state": 7,
+      "is_our_offer": false, "time_created": 0, "time_updated": 0
+    } } }"#).unwrap().unwrap();
+
+    assert_eq!(offer.state, TradeOfferState::Declined);
+    assert_eq!(parse_trade_offer(r#"{ "response": {} }"#), Ok(None));
+  }
+
+  #[test]
+  fn empty_and_malformed_responses() {
+    let offers = parse_trade_offers(r#"{ "response": {} }"#).unwrap();
+    assert!(offers.sent.is_empty() && offers.received.is_empty());
+    assert_eq!(offers.next_cursor, None);
+
+    assert!(matches!(parse_trade_offers("<html>Access Denied</html>"), Err(OfferError::Parse(_))));
+    assert!(matches!(parse_trade_offers(r#"{ "response": { "trade_offers_sent": [{ "tradeofferid": 1 }] } }"#), Err(OfferError::Parse(_))));
+  }
+
+  #[test]
+  fn pages_until_cursor_runs_out() {
+    let mut options = GetTradeOffersOptions::new();
+
+    assert!(next_page(&mut options, Some(100)));
+    assert_eq!(options.cursor, Some(100));
+    assert!(!next_page(&mut options, Some(100)));
+    assert!(!next_page(&mut options, Some(0)));
+    assert!(!next_page(&mut options, None));
+    assert_eq!(options.cursor, Some(100));
+  }
+
+  #[test]
+  fn steam_error_eresult() {
+    let error = SteamError::parse("There was an error sending your trade offer.  Please try again later. (26)".to_string());
+    assert_eq!(error.eresult, Some(26));
+
+    assert_eq!(SteamError::parse("Something went wrong (15) ".to_string()).eresult, Some(15));
+    assert_eq!(SteamError::parse("(15) is not at the end".to_string()).eresult, None);
+    assert_eq!(SteamError::parse("No code here".to_string()), SteamError { eresult: None, message: "No code here".to_string() });
+  }
+}
@@ -0,0 +1,329 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use super::Inventory::UnauthorizedResponse;
+use super::offers::{self, OfferError};
+
+const INDIVIDUAL_BASE: u64 = 76561197960265728;
+
+static STEAM2: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^STEAM_([0-5]):([01]):(\d+)$").unwrap());
+static STEAM3: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[?([IUMGAPCgTLca]):([0-5]):(\d+)(?::(\d+))?\]?$").unwrap());
+static PROFILE_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:https?://)?steamcommunity\.com/profiles/(\d+)/?$").unwrap());
+static VANITY_URL: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^(?:https?://)?steamcommunity\.com/id/([^/]+)/?$").unwrap());
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct SteamId(u64);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SteamIdError {
+  Invalid(String),
+  VanityNotFound(String),
+  Request(OfferError),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct ResolveVanityUrlQuery<'a> {
+  vanityurl: &'a str,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct ResolveVanityUrlResponse {
+  response: ResolveVanityUrlData,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct ResolveVanityUrlData {
+  success: i32,
+  steamid: Option<String>,
+}
+
+impl SteamId {
+  pub fn from_steam64(steam_id: u64) -> SteamId {
+    SteamId(steam_id)
+  }
+
+  pub fn from_account_id(account_id: u32) -> SteamId {
+    SteamId(INDIVIDUAL_BASE + account_id as u64)
+  }
+
+  pub fn from_parts(universe: u8, account_type: u8, instance: u32, account_id: u32) -> SteamId {
+    SteamId(((universe as u64) << 56) | (((account_type & 0xF) as u64) << 52) | (((instance & 0xFFFFF) as u64) << 32) | account_id as u64)
+  }
+
+  pub fn steam64(self) -> u64 {
+    self.0
+  }
+
+  pub fn account_id(self) -> u32 {
+    (self.0 & 0xFFFFFFFF) as u32
+  }
+
+  pub fn instance(self) -> u32 {
+    ((self.0 >> 32) & 0xFFFFF) as u32
+  }
+
+  pub fn account_type(self) -> u8 {
+    ((self.0 >> 52) & 0xF) as u8
+  }
+
+  pub fn universe(self) -> u8 {
+    (self.0 >> 56) as u8
+  }
+
+  pub fn to_steam2(self) -> String {
+    let account_id = self.account_id();
+    format!("STEAM_0:{}:{}", account_id & 1, account_id >> 1)
+  }
+
+  pub fn to_steam3(self) -> String {
+    let letter = type_to_letter(self.account_type(), self.instance());
+
+    match letter {
+      'M' | 'A' => format!("[{}:{}:{}:{}]", letter, self.universe(), self.account_id(), self.instance()),
+      _ => format!("[{}:{}:{}]", letter, self.universe(), self.account_id()),
+    }
+  }
+
+  pub fn parse(steam_id: &str) -> Result<SteamId, SteamIdError> {
+    let steam_id = steam_id.trim();
+
+    if let Ok(id) = steam_id.parse::<u64>() {
+      return match id {
+        0 => Err(SteamIdError::Invalid(steam_id.to_string())),
+        id if id <= u32::MAX as u64 => Ok(SteamId::from_account_id(id as u32)),
+        id => Ok(SteamId(id)),
+      };
+    }
+
+    if let Some(c) = STEAM2.captures(steam_id) {
+      let universe = match c[1].parse::<u8>().unwrap() {
+        0 => 1,
+        u => u,
+      };
+      let y = c[2].parse::<u32>().unwrap();
+      let z = match c[3].parse::<u32>() {
+        Ok(z) if z <= u32::MAX >> 1 => z,
+        _ => return Err(SteamIdError::Invalid(steam_id.to_string()))
+      };
+
+      return Ok(SteamId::from_parts(universe, 1, 1, z * 2 + y));
+    }
+
+    if let Some(c) = STEAM3.captures(steam_id) {
+      let letter = c[1].chars().next().unwrap();
+      let universe = c[2].parse::<u8>().unwrap();
+      let account_id = match c[3].parse::<u32>() {
+        Ok(id) => id,
+        Err(_) => return Err(SteamIdError::Invalid(steam_id.to_string()))
+      };
+      let (account_type, default_instance) = letter_to_type(letter);
+      let instance = match c.get(4) {
+        Some(i) => match i.as_str().parse::<u32>() {
+          Ok(i) => i,
+          Err(_) => return Err(SteamIdError::Invalid(steam_id.to_string()))
+        },
+        None => default_instance,
+      };
+
+      return Ok(SteamId::from_parts(universe, account_type, instance, account_id));
+    }
+
+    if let Some(c) = PROFILE_URL.captures(steam_id) {
+      return SteamId::parse(&c[1]);
+    }
+
+    Err(SteamIdError::Invalid(steam_id.to_string()))
+  }
+
+  // Accepts everything `parse` does plus vanity names and /id/ profile urls
+  pub async fn resolve(api_key: &String, steam_id: &str) -> Result<SteamId, SteamIdError> {
+    if let Ok(id) = SteamId::parse(steam_id) {
+      return Ok(id);
+    }
+
+    let vanity_url = match VANITY_URL.captures(steam_id.trim()) {
+      Some(c) => c[1].to_string(),
+      None => steam_id.trim().to_string(),
+    };
+
+    SteamId::resolve_vanity_url(api_key, &vanity_url).await
+  }
+
+  pub async fn resolve_vanity_url(api_key: &String, vanity_url: &str) -> Result<SteamId, SteamIdError> {
+    let query = ResolveVanityUrlQuery { vanityurl: vanity_url };
+
+    let text = offers::econ_get("https://api.steampowered.com/ISteamUser/ResolveVanityURL/v1/", api_key, &query).await?;
+
+    let response = match serde_json::from_str::<ResolveVanityUrlResponse>(&text) {
+      Ok(res) => res.response,
+      Err(e) => return Err(SteamIdError::Request(OfferError::Parse(e.to_string())))
+    };
+
+    match (response.success, response.steamid) {
+      (1, Some(steam_id)) => SteamId::parse(&steam_id),
+      _ => Err(SteamIdError::VanityNotFound(vanity_url.to_string()))
+    }
+  }
+}
+
+impl From<OfferError> for SteamIdError {
+  fn from(e: OfferError) -> SteamIdError {
+    SteamIdError::Request(e)
+  }
+}
+
+impl From<UnauthorizedResponse> for SteamIdError {
+  fn from(response: UnauthorizedResponse) -> SteamIdError {
+    SteamIdError::Request(OfferError::Unauthorized(response))
+  }
+}
+
+fn letter_to_type(letter: char) -> (u8, u32) {
+  match letter {
+    'I' => (0, 0),
+    'U' => (1, 1),
+    'M' => (2, 0),
+    'G' => (3, 1),
+    'A' => (4, 0),
+    'P' => (5, 0),
+    'C' => (6, 0),
+    'g' => (7, 0),
+    // Chat ids carry their kind in the instance flags
+    'T' => (8, 0),
+    'c' => (8, 0x80000),
+    'L' => (8, 0x40000),
+    'a' => (10, 0),
+    _ => (0, 0),
+  }
+}
+
+fn type_to_letter(account_type: u8, instance: u32) -> char {
+  match account_type {
+    0 => 'I',
+    1 => 'U',
+    2 => 'M',
+    3 => 'G',
+    4 => 'A',
+    5 => 'P',
+    6 => 'C',
+    7 => 'g',
+    8 if instance & 0x80000 != 0 => 'c',
+    8 if instance & 0x40000 != 0 => 'L',
+    8 => 'T',
+    10 => 'a',
+    _ => 'i',
+  }
+}
+
+impl FromStr for SteamId {
+  type Err = SteamIdError;
+
+  fn from_str(s: &str) -> Result<SteamId, SteamIdError> {
+    SteamId::parse(s)
+  }
+}
+
+impl TryFrom<String> for SteamId {
+  type Error = SteamIdError;
+
+  fn try_from(s: String) -> Result<SteamId, SteamIdError> {
+    SteamId::parse(&s)
+  }
+}
+
+impl From<SteamId> for String {
+  fn from(steam_id: SteamId) -> String {
+    steam_id.to_string()
+  }
+}
+
+impl fmt::Display for SteamId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl fmt::Display for SteamIdError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use proptest::prelude::*;
+
+  // Letters whose instance is implied, paired with the type and instance they stand for
+  const IMPLIED_INSTANCE: [(u8, u32); 9] = [(0, 0), (1, 1), (3, 1), (5, 0), (6, 0), (7, 0), (8, 0), (8, 0x40000), (8, 0x80000)];
+
+  #[test]
+  fn parses_known_formats() {
+    let expected = SteamId::from_steam64(76561197960287930);
+
+    assert_eq!(SteamId::parse("76561197960287930").unwrap(), expected);
+    assert_eq!(SteamId::parse("22202").unwrap(), expected);
+    assert_eq!(SteamId::parse("STEAM_0:0:11101").unwrap(), expected);
+    assert_eq!(SteamId::parse("STEAM_1:0:11101").unwrap(), expected);
+    assert_eq!(SteamId::parse("[U:1:22202]").unwrap(), expected);
+    assert_eq!(SteamId::parse("U:1:22202").unwrap(), expected);
+    assert_eq!(SteamId::parse("https://steamcommunity.com/profiles/76561197960287930/").unwrap(), expected);
+
+    assert_eq!(expected.to_steam2(), "STEAM_0:0:11101");
+    assert_eq!(expected.to_steam3(), "[U:1:22202]");
+  }
+
+  #[test]
+  fn rejects_garbage() {
+    assert!(SteamId::parse("0").is_err());
+    assert!(SteamId::parse("").is_err());
+    assert!(SteamId::parse("STEAM_0:2:1").is_err());
+    assert!(SteamId::parse("[X:1:1]").is_err());
+    assert!(SteamId::parse("steamcommunity.com/id/gabelogannewell").is_err());
+  }
+
+  proptest! {
+    #[test]
+    fn steam64_round_trips(id in (u32::MAX as u64 + 1)..=u64::MAX) {
+      let steam_id = SteamId::from_steam64(id);
+      prop_assert_eq!(SteamId::parse(&steam_id.to_string()).unwrap(), steam_id);
+      prop_assert_eq!(steam_id.steam64(), id);
+    }
+
+    #[test]
+    fn account_id_round_trips(account_id in 1..=u32::MAX) {
+      let steam_id = SteamId::from_account_id(account_id);
+      prop_assert_eq!(steam_id.account_id(), account_id);
+      prop_assert_eq!(SteamId::parse(&account_id.to_string()).unwrap(), steam_id);
+    }
+
+    #[test]
+    fn steam2_round_trips(account_id in any::<u32>()) {
+      let steam_id = SteamId::from_account_id(account_id);
+      prop_assert_eq!(SteamId::parse(&steam_id.to_steam2()).unwrap(), steam_id);
+    }
+
+    #[test]
+    fn steam3_round_trips_with_implied_instance(universe in 0u8..=5, kind in 0..IMPLIED_INSTANCE.len(), account_id in any::<u32>()) {
+      let (account_type, instance) = IMPLIED_INSTANCE[kind];
+      let steam_id = SteamId::from_parts(universe, account_type, instance, account_id);
+      prop_assert_eq!(SteamId::parse(&steam_id.to_steam3()).unwrap(), steam_id);
+    }
+
+    #[test]
+    fn steam3_round_trips_with_explicit_instance(universe in 0u8..=5, account_type in prop::sample::select(vec![2u8, 4]), instance in 0u32..=0xFFFFF, account_id in any::<u32>()) {
+      let steam_id = SteamId::from_parts(universe, account_type, instance, account_id);
+      prop_assert_eq!(SteamId::parse(&steam_id.to_steam3()).unwrap(), steam_id);
+    }
+
+    #[test]
+    fn serde_round_trips(account_id in any::<u32>()) {
+      let steam_id = SteamId::from_account_id(account_id);
+      let json = serde_json::to_string(&steam_id).unwrap();
+      prop_assert_eq!(serde_json::from_str::<SteamId>(&json).unwrap(), steam_id);
+    }
+  }
+}
@@ -1,8 +1,24 @@
-use std::collections::{HashMap, BTreeSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::iter::FromIterator;
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
 use reqwest::{Client, StatusCode};
-use super::Inventory::UnauthorizedResponse;
+use regex::Regex;
+use super::Inventory::{Inventory, UnauthorizedResponse};
+use super::offers::{self, GetTradeOffersOptions, Offer, OfferError};
+use super::offer_state::{TradeOfferState, TradeOfferStateTracker};
+use super::trade_url::{TradeUrl, TradeUrlError};
+use super::steam_id::SteamId;
+
+pub const MAX_ITEMS_PER_OFFER: usize = 256;
+pub const MAX_MESSAGE_LENGTH: usize = 128;
+
+// Steam's time_created can lag behind the moment we recorded the send key
+const SEND_KEY_MARGIN_SECS: u64 = 300;
+
+static MY_ESCROW_DAYS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"var g_daysMyEscrow = (\d+);").unwrap());
+static THEIR_ESCROW_DAYS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"var g_daysTheirEscrow = (\d+);").unwrap());
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TradeOfferData {
@@ -29,11 +45,13 @@ pub struct OfferAsset {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TradeOffer {
-  pub partner: String,
+  pub partner: SteamId,
   pub tradeoffermessage: String,
   pub json_tradeoffer: TradeOfferData,
   pub trade_offer_create_params: TradeOfferCreateParams,
   pub trade_url: String,
+  #[serde(default)]
+  pub tradeofferid_countered: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -45,11 +63,14 @@ struct TradeOfferForm {
   pub json_tradeoffer: String,
   captcha: String,
   pub trade_offer_create_params: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tradeofferid_countered: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TradeOfferCreateParams {
-  pub trade_offer_access_token: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub trade_offer_access_token: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -60,24 +81,122 @@ pub struct TradeOfferSuccess {
   pub email_domain: Option<String>
 }
 
-impl TradeOffer {
-  pub fn new(trade_url: String) -> TradeOffer {
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SendKeyRecord {
+  pub key: String,
+  pub partner: SteamId,
+  pub assets: Vec<String>,
+  pub created_at: u64,
+  pub success: Option<TradeOfferSuccess>,
+}
 
-    let parsed_url: HashMap<String, String> = reqwest::Url::parse(&trade_url).unwrap().query_pairs().into_owned().collect();
+// Keys must be written before the request goes out so a crash mid-send can be recovered
+pub trait SendKeyStore {
+  fn get_send_key(&self, key: &str) -> Result<Option<SendKeyRecord>, String>;
+  fn begin_send_key(&self, record: &SendKeyRecord) -> Result<(), String>;
+  fn complete_send_key(&self, key: &str, success: &TradeOfferSuccess) -> Result<(), String>;
+}
 
-    let partnerid = match parsed_url.get("partner") {
-      Some(p) => p,
-      None => panic!("Failed to get partner Id")
-    };
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum IdempotentSendError {
+  // On OfferError::Network the key stays pending, retrying with the same key looks the offer up first
+  Offer(OfferError),
+  Store(String),
+  // The key was already used for a different partner or item set
+  KeyMismatch { key: String },
+}
+
+// Where send_idempotent picks up for a key
+#[derive(Clone, Debug, PartialEq)]
+enum SendKeyStep {
+  Sent(TradeOfferSuccess),
+  // Begun at this time but never completed
+  Pending(u64),
+  New,
+}
 
-    let access_token = match parsed_url.get("token") {
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeHoldDurations {
+  pub my_escrow_seconds: u64,
+  pub their_escrow_seconds: u64,
+  pub both_escrow_seconds: u64,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct TradeHoldDurationsQuery<'a> {
+  steamid_target: &'a SteamId,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  trade_offer_access_token: &'a Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct TradeHoldDurationsResponse {
+  response: TradeHoldDurationsData,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct TradeHoldDurationsData {
+  my_escrow: EscrowDuration,
+  their_escrow: EscrowDuration,
+  both_escrow: EscrowDuration,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EscrowDuration {
+  escrow_end_duration_seconds: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum OfferSide {
+  Me,
+  Them
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TradeOfferValidationError {
+  EmptyOffer,
+  SelfTrade,
+  MessageTooLong { length: usize, max: usize },
+  TooManyItems { count: usize, max: usize },
+  DuplicateAsset { side: OfferSide, assetid: String },
+  AssetNotFound { side: OfferSide, assetid: String },
+  AssetNotTradable { side: OfferSide, assetid: String },
+}
+
+impl TradeOffer {
+  pub fn new(trade_url: String) -> Result<TradeOffer, TradeUrlError> {
+    let parsed_url = TradeUrl::parse(&trade_url)?;
+
+    let steam_id = parsed_url.steam_id();
+
+    let access_token = match parsed_url.token {
       Some(t) => t,
-      None => panic!("Failed to get partner Id")
+      None => return Err(TradeUrlError::MissingToken)
     };
 
-    let steam_id: u64 = super::convert_parterid_to_steamid(partnerid);
+    Ok(TradeOffer { partner: steam_id, tradeoffermessage: String::new(), json_tradeoffer: TradeOfferData::new(), trade_offer_create_params: TradeOfferCreateParams { trade_offer_access_token: Some(access_token) }, trade_url, tradeofferid_countered: None })
+  }
+
+  // Friends can be sent offers without a trade token
+  pub fn new_with_steam_id(partner: SteamId, token: Option<String>) -> TradeOffer {
+    let trade_url = TradeUrl::from_steam_id(partner, token.to_owned()).to_string();
 
-    TradeOffer { partner: steam_id.to_string(), tradeoffermessage: String::new(), json_tradeoffer: TradeOfferData::new(), trade_offer_create_params: TradeOfferCreateParams { trade_offer_access_token: access_token.to_owned() }, trade_url }
+    TradeOffer { partner, tradeoffermessage: String::new(), json_tradeoffer: TradeOfferData::new(), trade_offer_create_params: TradeOfferCreateParams { trade_offer_access_token: token }, trade_url, tradeofferid_countered: None }
+  }
+
+  pub fn counter(offer: &Offer) -> TradeOffer {
+    let mut json_tradeoffer = TradeOfferData::new();
+    json_tradeoffer.me.assets = offer.items_to_give.iter().map(|i| i.to_offer_asset()).collect();
+    json_tradeoffer.them.assets = offer.items_to_receive.iter().map(|i| i.to_offer_asset()).collect();
+
+    TradeOffer {
+      partner: offer.partner,
+      tradeoffermessage: String::new(),
+      json_tradeoffer,
+      trade_offer_create_params: TradeOfferCreateParams { trade_offer_access_token: None },
+      trade_url: format!("https://steamcommunity.com/tradeoffer/{}/", offer.tradeofferid),
+      tradeofferid_countered: Some(offer.tradeofferid.to_owned()),
+    }
   }
 
   pub fn set_trade_message(&mut self, message: String) {
@@ -118,38 +237,269 @@ impl TradeOffer {
     self.json_tradeoffer.them.assets.retain(|a| !to_remove.contains(&a.assetid))
   }
 
-  pub async fn send(&mut self, cookie: &String) -> Result<TradeOfferSuccess, UnauthorizedResponse> {
+  pub fn validate(&self, self_steam_id: &SteamId, self_inventory: &Inventory, partner_inventory: &Inventory) -> Result<(), TradeOfferValidationError> {
+    let me = &self.json_tradeoffer.me.assets;
+    let them = &self.json_tradeoffer.them.assets;
+
+    if me.is_empty() && them.is_empty() {
+      return Err(TradeOfferValidationError::EmptyOffer);
+    }
+
+    if &self.partner == self_steam_id {
+      return Err(TradeOfferValidationError::SelfTrade);
+    }
+
+    let length = self.tradeoffermessage.chars().count();
+    if length > MAX_MESSAGE_LENGTH {
+      return Err(TradeOfferValidationError::MessageTooLong { length, max: MAX_MESSAGE_LENGTH });
+    }
+
+    let count = me.len() + them.len();
+    if count > MAX_ITEMS_PER_OFFER {
+      return Err(TradeOfferValidationError::TooManyItems { count, max: MAX_ITEMS_PER_OFFER });
+    }
+
+    validate_assets(OfferSide::Me, me, &InventoryIndex::new(self_inventory))?;
+    validate_assets(OfferSide::Them, them, &InventoryIndex::new(partner_inventory))?;
+
+    Ok(())
+  }
+
+  pub async fn get_trade_hold_durations(&self, api_key: &String) -> Result<TradeHoldDurations, OfferError> {
+    let query = TradeHoldDurationsQuery { steamid_target: &self.partner, trade_offer_access_token: &self.trade_offer_create_params.trade_offer_access_token };
+
+    let text = offers::econ_get("https://api.steampowered.com/IEconService/GetTradeHoldDurations/v1/", api_key, &query).await?;
+
+    let durations = match serde_json::from_str::<TradeHoldDurationsResponse>(&text) {
+      Ok(res) => res.response,
+      Err(e) => return Err(OfferError::Parse(e.to_string()))
+    };
+
+    Ok(TradeHoldDurations {
+      my_escrow_seconds: durations.my_escrow.escrow_end_duration_seconds,
+      their_escrow_seconds: durations.their_escrow.escrow_end_duration_seconds,
+      both_escrow_seconds: durations.both_escrow.escrow_end_duration_seconds,
+    })
+  }
+
+  // Falls back to the escrow days embedded in the new trade offer page when no api key is available.
+  // Always the new offer page for the partner, a counter's trade_url points at the offer being countered
+  pub async fn get_trade_hold_days(&self, cookie: &String) -> Result<TradeHoldDurations, OfferError> {
+    let url = TradeUrl::from_steam_id(self.partner, self.trade_offer_create_params.trade_offer_access_token.to_owned()).to_string();
+
     let client = Client::new();
+    let res = match client.get(url).header("Cookie", cookie).send().await {
+      Ok(res) => res,
+      Err(e) => return Err(OfferError::Network(e.to_string()))
+    };
+
+    let status = res.status().to_owned();
+    let text = match res.text().await {
+      Ok(text) => text,
+      Err(e) => return Err(OfferError::Network(e.to_string()))
+    };
+
+    match status {
+      StatusCode::OK => (),
+      _ => return Err(OfferError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }))
+    }
+
+    parse_trade_hold_days(&text)
+  }
+
+  // A Network error means Steam may or may not have created the offer, see send_idempotent
+  pub async fn send(&mut self, cookie: &String) -> Result<TradeOfferSuccess, OfferError> {
+    let form_data = TradeOfferForm::from(&*self);
+
+    let text = offers::community_post("https://steamcommunity.com/tradeoffer/new/send", &self.trade_url, cookie, &form_data.sessionid, &form_data).await?;
+
+    match serde_json::from_str::<TradeOfferSuccess>(&text) {
+      Ok(success) => Ok(success),
+      Err(e) => Err(OfferError::Parse(e.to_string()))
+    }
+  }
+
+  // Sends a counter offer and links it to the offer it replaces once Steam has created it
+  pub async fn send_counter(&mut self, cookie: &String, tracker: &Mutex<TradeOfferStateTracker>) -> Result<TradeOfferSuccess, OfferError> {
+    let success = self.send(cookie).await?;
+
+    if let Some(original) = &self.tradeofferid_countered {
+      tracker.lock().unwrap().record_counter(original, &success.tradeofferid);
+    }
+
+    Ok(success)
+  }
 
-    let form_data = TradeOfferForm::from(&self);
+  // Kept apart from send, recovering a pending key needs a Web API key to look the offer up
+  // and a store that outlives the process, which one-off sends and counters don't have
+  pub async fn send_idempotent(&mut self, cookie: &String, api_key: &String, key: &str, store: &(dyn SendKeyStore + Sync)) -> Result<TradeOfferSuccess, IdempotentSendError> {
+    let assets = asset_fingerprint(&self.json_tradeoffer.me.assets, &self.json_tradeoffer.them.assets);
+
+    match self.resume_send_key(key, &assets, store)? {
+      SendKeyStep::Sent(success) => return Ok(success),
+      SendKeyStep::Pending(created_at) => {
+        // A previous attempt may have reached Steam without us seeing the response
+        if let Some(success) = self.find_sent(api_key, &assets, created_at).await? {
+          store.complete_send_key(key, &success).map_err(IdempotentSendError::Store)?;
+          return Ok(success);
+        }
+      },
+      SendKeyStep::New => (),
+    }
+
+    let success = self.send(cookie).await.map_err(IdempotentSendError::Offer)?;
+    store.complete_send_key(key, &success).map_err(IdempotentSendError::Store)?;
+
+    Ok(success)
+  }
+
+  // Begins the key when it's new, everything after this needs Steam
+  fn resume_send_key(&self, key: &str, assets: &Vec<String>, store: &(dyn SendKeyStore + Sync)) -> Result<SendKeyStep, IdempotentSendError> {
+    match store.get_send_key(key).map_err(IdempotentSendError::Store)? {
+      Some(record) => {
+        if record.partner != self.partner || record.assets != *assets {
+          return Err(IdempotentSendError::KeyMismatch { key: key.to_owned() });
+        }
+
+        match record.success {
+          Some(success) => Ok(SendKeyStep::Sent(success)),
+          None => Ok(SendKeyStep::Pending(record.created_at)),
+        }
+      },
+      None => {
+        let record = SendKeyRecord {
+          key: key.to_owned(),
+          partner: self.partner,
+          assets: assets.to_owned(),
+          created_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+          success: None,
+        };
+        store.begin_send_key(&record).map_err(IdempotentSendError::Store)?;
+
+        Ok(SendKeyStep::New)
+      }
+    }
+  }
 
-    let cookie = format!("{}sessionid={};", cookie, &form_data.sessionid);
+  // Active offers plus everything updated since the cutoff, which covers offers that were already accepted or declined
+  async fn find_sent(&self, api_key: &String, assets: &Vec<String>, since: u64) -> Result<Option<TradeOfferSuccess>, IdempotentSendError> {
+    let mut options = GetTradeOffersOptions::new();
+    options.get_received_offers = false;
+    options.get_descriptions = false;
+    options.active_only = true;
+    options.time_historical_cutoff = Some(since.saturating_sub(SEND_KEY_MARGIN_SECS));
 
-    let res = client.post("https://steamcommunity.com/tradeoffer/new/send")
-      .header("Referer", &self.trade_url)
-      .header("Cookie", cookie)
-      .form(&form_data)
-      .send().await.expect("Failed to send request");
+    loop {
+      let page = offers::get_trade_offers(api_key, &options).await.map_err(IdempotentSendError::Offer)?;
 
-      let status = res.status().to_owned();
-      let text = res.text().await.expect("Failed to get payload");
-  
-      match status {
-        StatusCode::OK => (),
-        _ => return Err(UnauthorizedResponse { status: status.to_string(), error: text })
+      if let Some(success) = self.find_sent_in(page.sent, assets, since) {
+        return Ok(Some(success));
       }
 
-    let inventory = match serde_json::from_str::<TradeOfferSuccess>(&text) {
-      Ok(inv) => inv,
-      Err(e) => {
-        println!("{}",&text);
-        panic!("{}",e)
+      if !offers::next_page(&mut options, page.next_cursor) {
+        return Ok(None);
       }
+    }
+  }
+
+  fn find_sent_in(&self, sent: Vec<Offer>, assets: &Vec<String>, since: u64) -> Option<TradeOfferSuccess> {
+    let found = sent.into_iter().find(|o| {
+      let give = o.items_to_give.iter().map(|i| i.to_offer_asset()).collect::<Vec<OfferAsset>>();
+      let receive = o.items_to_receive.iter().map(|i| i.to_offer_asset()).collect::<Vec<OfferAsset>>();
+
+      o.partner == self.partner
+        && o.time_created + SEND_KEY_MARGIN_SECS >= since
+        && asset_fingerprint(&give, &receive) == *assets
+    })?;
+
+    Some(TradeOfferSuccess {
+      tradeofferid: found.tradeofferid,
+      need_mobile_confirmation: Some(found.state == TradeOfferState::CreatedNeedsConfirmation),
+      needs_email_confirmation: None,
+      email_domain: None,
+    })
+  }
+
+}
+
+// Order independent, so the offer we built and the one Steam returns compare equal
+fn asset_fingerprint(me: &[OfferAsset], them: &[OfferAsset]) -> Vec<String> {
+  let me = me.iter().map(|a| format!("me:{}:{}:{}", a.appid, a.contextid, a.assetid));
+  let them = them.iter().map(|a| format!("them:{}:{}:{}", a.appid, a.contextid, a.assetid));
+
+  let mut fingerprint = Vec::from_iter(me.chain(them));
+  fingerprint.sort();
+  fingerprint
+}
+
+impl TradeHoldDurations {
+  pub fn has_hold(&self) -> bool {
+    self.my_escrow_seconds > 0 || self.their_escrow_seconds > 0 || self.both_escrow_seconds > 0
+  }
+
+  pub fn within(&self, max_days: u64) -> bool {
+    self.my_escrow_seconds.max(self.their_escrow_seconds).max(self.both_escrow_seconds) <= max_days * 86400
+  }
+}
+
+// Bulk offers validate hundreds of assets, so both lookups are built once per inventory
+struct InventoryIndex<'a> {
+  assets: HashMap<(String, &'a str, &'a str), (&'a str, &'a str)>,
+  tradable: HashSet<(&'a str, &'a str)>,
+}
+
+impl<'a> InventoryIndex<'a> {
+  fn new(inventory: &'a Inventory) -> InventoryIndex<'a> {
+    let assets = inventory.assets.iter()
+      .map(|a| ((a.appid.to_string(), a.contextid.as_str(), a.assetid.as_str()), (a.classid.as_str(), a.instanceid.as_str())))
+      .collect();
+
+    let tradable = inventory.descriptions.iter()
+      .filter(|d| d.tradable == 1)
+      .map(|d| (d.classid.as_str(), d.instanceid.as_str()))
+      .collect();
+
+    InventoryIndex { assets, tradable }
+  }
+}
+
+fn validate_assets(side: OfferSide, assets: &Vec<OfferAsset>, inventory: &InventoryIndex) -> Result<(), TradeOfferValidationError> {
+  let mut seen: BTreeSet<&String> = BTreeSet::new();
+
+  for offer_asset in assets {
+    if !seen.insert(&offer_asset.assetid) {
+      return Err(TradeOfferValidationError::DuplicateAsset { side, assetid: offer_asset.assetid.to_owned() });
+    }
+
+    let key = (offer_asset.appid.to_owned(), offer_asset.contextid.as_str(), offer_asset.assetid.as_str());
+
+    let class = match inventory.assets.get(&key) {
+      Some(class) => class,
+      None => return Err(TradeOfferValidationError::AssetNotFound { side, assetid: offer_asset.assetid.to_owned() })
     };
 
-    Ok(inventory)
+    if !inventory.tradable.contains(class) {
+      return Err(TradeOfferValidationError::AssetNotTradable { side, assetid: offer_asset.assetid.to_owned() });
+    }
   }
 
+  Ok(())
+}
+
+fn parse_trade_hold_days(page: &str) -> Result<TradeHoldDurations, OfferError> {
+  let my_days = MY_ESCROW_DAYS.captures(page).and_then(|c| c[1].parse::<u64>().ok());
+  let their_days = THEIR_ESCROW_DAYS.captures(page).and_then(|c| c[1].parse::<u64>().ok());
+
+  let (my_days, their_days) = match (my_days, their_days) {
+    (Some(m), Some(t)) => (m, t),
+    _ => return Err(OfferError::Parse("Failed to find escrow days in trade offer page".to_string()))
+  };
+
+  Ok(TradeHoldDurations {
+    my_escrow_seconds: my_days * 86400,
+    their_escrow_seconds: their_days * 86400,
+    both_escrow_seconds: my_days.max(their_days) * 86400,
+  })
 }
 
 impl TradeOfferData {
@@ -185,6 +535,288 @@ impl TradeOfferForm {
       json_tradeoffer: json_data,
       captcha: "".to_string(),
       trade_offer_create_params: token_data,
+      tradeofferid_countered: trade_offer.tradeofferid_countered.to_owned(),
     }
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_support::{description, inventory, offer, offer_item};
+
+  #[test]
+  fn counter_prefills_both_sides() {
+    let mut original = offer(
+      vec![offer_item("10", Some(description("1", "AK-47 | Redline (Field-Tested)", &[])))],
+      vec![offer_item("20", None), offer_item("21", None)],
+    );
+    original.tradeofferid = "6000000001".to_string();
+
+    let counter = TradeOffer::counter(&original);
+
+    assert_eq!(counter.partner, original.partner);
+    assert_eq!(counter.tradeofferid_countered, Some("6000000001".to_string()));
+    assert_eq!(counter.trade_url, "https://steamcommunity.com/tradeoffer/6000000001/");
+    assert_eq!(counter.json_tradeoffer.me.assets, vec![OfferAsset::new("730".to_string(), "2".to_string(), "1".to_string(), "10".to_string())]);
+    assert_eq!(counter.json_tradeoffer.them.assets.iter().map(|a| a.assetid.as_str()).collect::<Vec<_>>(), vec!["20", "21"]);
+
+    let form = TradeOfferForm::from(&counter);
+    assert_eq!(form.tradeofferid_countered, Some("6000000001".to_string()));
+  }
+
+  #[test]
+  fn parses_trade_hold_days() {
+    let page = r#"<script>
+      var g_daysMyEscrow = 0;
+      var g_daysTheirEscrow = 15;
+    </script>"#;
+
+    let durations = parse_trade_hold_days(page).unwrap();
+    assert_eq!(durations, TradeHoldDurations { my_escrow_seconds: 0, their_escrow_seconds: 15 * 86400, both_escrow_seconds: 15 * 86400 });
+    assert!(durations.has_hold());
+    assert!(!durations.within(7));
+    assert!(durations.within(15));
+
+    let none = parse_trade_hold_days("var g_daysMyEscrow = 0;\nvar g_daysTheirEscrow = 0;").unwrap();
+    assert!(!none.has_hold());
+  }
+
+  #[test]
+  fn missing_escrow_days_is_a_parse_error() {
+    assert!(matches!(parse_trade_hold_days("<html>Sign In</html>"), Err(OfferError::Parse(_))));
+    assert!(matches!(parse_trade_hold_days("var g_daysMyEscrow = 0;"), Err(OfferError::Parse(_))));
+    assert!(matches!(parse_trade_hold_days("var g_daysMyEscrow = 0;\nvar g_daysTheirEscrow = x;"), Err(OfferError::Parse(_))));
+  }
+
+  fn asset(appid: &str, contextid: &str, assetid: &str) -> OfferAsset {
+    OfferAsset::new(appid.to_string(), contextid.to_string(), "1".to_string(), assetid.to_string())
+  }
+
+  fn validation_fixture() -> (SteamId, Inventory, Inventory, TradeOffer) {
+    let mut untradable = description("3", "Souvenir Package", &[]);
+    untradable.tradable = 0;
+
+    let ours = inventory(vec![("10", description("1", "AK-47 | Redline (Field-Tested)", &[])), ("11", untradable)]);
+    let theirs = inventory(vec![("20", description("2", "Sticker | Crown (Foil)", &[]))]);
+
+    let mut trade_offer = TradeOffer::new_with_steam_id(SteamId::from_account_id(22202), None);
+    trade_offer.add_self_item(asset("730", "2", "10"));
+    trade_offer.add_partner_item(asset("730", "2", "20"));
+
+    (SteamId::from_account_id(1), ours, theirs, trade_offer)
+  }
+
+  #[test]
+  fn validates_a_good_offer() {
+    let (me, ours, theirs, trade_offer) = validation_fixture();
+    assert_eq!(trade_offer.validate(&me, &ours, &theirs), Ok(()));
+  }
+
+  #[test]
+  fn rejects_empty_and_self_trades() {
+    let (me, ours, theirs, _) = validation_fixture();
+
+    let empty = TradeOffer::new_with_steam_id(SteamId::from_account_id(22202), None);
+    assert_eq!(empty.validate(&me, &ours, &theirs), Err(TradeOfferValidationError::EmptyOffer));
+
+    let mut to_self = TradeOffer::new_with_steam_id(me, None);
+    to_self.add_self_item(asset("730", "2", "10"));
+    assert_eq!(to_self.validate(&me, &ours, &theirs), Err(TradeOfferValidationError::SelfTrade));
+  }
+
+  #[test]
+  fn rejects_long_messages_and_too_many_items() {
+    let (me, ours, theirs, mut trade_offer) = validation_fixture();
+
+    trade_offer.set_trade_message("é".repeat(MAX_MESSAGE_LENGTH));
+    assert_eq!(trade_offer.validate(&me, &ours, &theirs), Ok(()));
+    trade_offer.set_trade_message("é".repeat(MAX_MESSAGE_LENGTH + 1));
+    assert_eq!(trade_offer.validate(&me, &ours, &theirs), Err(TradeOfferValidationError::MessageTooLong { length: MAX_MESSAGE_LENGTH + 1, max: MAX_MESSAGE_LENGTH }));
+
+    let (me, ours, theirs, mut trade_offer) = validation_fixture();
+    trade_offer.add_partner_items((0..MAX_ITEMS_PER_OFFER - 1).map(|i| asset("730", "2", &format!("9{}", i))).collect());
+    assert_eq!(trade_offer.validate(&me, &ours, &theirs), Err(TradeOfferValidationError::TooManyItems { count: MAX_ITEMS_PER_OFFER + 1, max: MAX_ITEMS_PER_OFFER }));
+  }
+
+  #[test]
+  fn rejects_duplicate_assets() {
+    let (me, ours, theirs, mut trade_offer) = validation_fixture();
+    trade_offer.add_partner_item(asset("730", "2", "20"));
+
+    assert_eq!(trade_offer.validate(&me, &ours, &theirs), Err(TradeOfferValidationError::DuplicateAsset { side: OfferSide::Them, assetid: "20".to_string() }));
+  }
+
+  #[test]
+  fn rejects_assets_not_in_the_inventory() {
+    let (me, ours, theirs, _) = validation_fixture();
+
+    for (appid, contextid, assetid) in [("730", "2", "99"), ("440", "2", "10"), ("730", "6", "10")] {
+      let mut trade_offer = TradeOffer::new_with_steam_id(SteamId::from_account_id(22202), None);
+      trade_offer.add_self_item(asset(appid, contextid, assetid));
+
+      assert_eq!(trade_offer.validate(&me, &ours, &theirs), Err(TradeOfferValidationError::AssetNotFound { side: OfferSide::Me, assetid: assetid.to_string() }));
+    }
+
+    // Our item on their side
+    let mut trade_offer = TradeOffer::new_with_steam_id(SteamId::from_account_id(22202), None);
+    trade_offer.add_partner_item(asset("730", "2", "10"));
+    assert_eq!(trade_offer.validate(&me, &ours, &theirs), Err(TradeOfferValidationError::AssetNotFound { side: OfferSide::Them, assetid: "10".to_string() }));
+  }
+
+  #[test]
+  fn rejects_untradable_assets() {
+    let (me, ours, theirs, mut trade_offer) = validation_fixture();
+    trade_offer.add_self_item(asset("730", "2", "11"));
+
+    assert_eq!(trade_offer.validate(&me, &ours, &theirs), Err(TradeOfferValidationError::AssetNotTradable { side: OfferSide::Me, assetid: "11".to_string() }));
+  }
+
+  #[test]
+  fn friend_offer_without_token() {
+    let trade_offer = TradeOffer::new_with_steam_id(SteamId::from_account_id(22202), None);
+    let form = TradeOfferForm::from(&trade_offer);
+
+    assert_eq!(form.trade_offer_create_params, "{}");
+    assert_eq!(form.partner, SteamId::from_account_id(22202).to_string());
+    assert_eq!(trade_offer.trade_url, "https://steamcommunity.com/tradeoffer/new/?partner=22202");
+  }
+
+  #[test]
+  fn friend_offer_with_token() {
+    let trade_offer = TradeOffer::new_with_steam_id(SteamId::from_account_id(22202), Some("gn-X8Nub".to_string()));
+    let form = TradeOfferForm::from(&trade_offer);
+
+    assert_eq!(form.trade_offer_create_params, r#"{"trade_offer_access_token":"gn-X8Nub"}"#);
+    assert_eq!(trade_offer.trade_url, "https://steamcommunity.com/tradeoffer/new/?partner=22202&token=gn-X8Nub");
+  }
+
+  #[derive(Default)]
+  struct MemoryStore {
+    records: Mutex<HashMap<String, SendKeyRecord>>,
+  }
+
+  impl SendKeyStore for MemoryStore {
+    fn get_send_key(&self, key: &str) -> Result<Option<SendKeyRecord>, String> {
+      Ok(self.records.lock().unwrap().get(key).cloned())
+    }
+
+    fn begin_send_key(&self, record: &SendKeyRecord) -> Result<(), String> {
+      self.records.lock().unwrap().insert(record.key.to_owned(), record.clone());
+      Ok(())
+    }
+
+    fn complete_send_key(&self, key: &str, success: &TradeOfferSuccess) -> Result<(), String> {
+      let mut records = self.records.lock().unwrap();
+      let record = match records.get_mut(key) {
+        Some(record) => record,
+        None => return Err(format!("unknown key {}", key)),
+      };
+
+      record.success = Some(success.clone());
+      Ok(())
+    }
+  }
+
+  fn keyed_offer() -> TradeOffer {
+    let mut trade_offer = TradeOffer::new_with_steam_id(SteamId::from_account_id(22202), None);
+    trade_offer.add_self_item(asset("730", "2", "10"));
+    trade_offer.add_partner_item(asset("730", "2", "20"));
+    trade_offer
+  }
+
+  fn sent(tradeofferid: &str) -> TradeOfferSuccess {
+    TradeOfferSuccess { tradeofferid: tradeofferid.to_string(), need_mobile_confirmation: Some(false), needs_email_confirmation: None, email_domain: None }
+  }
+
+  fn record(trade_offer: &TradeOffer, created_at: u64, success: Option<TradeOfferSuccess>) -> SendKeyRecord {
+    SendKeyRecord {
+      key: "key".to_string(),
+      partner: trade_offer.partner,
+      assets: asset_fingerprint(&trade_offer.json_tradeoffer.me.assets, &trade_offer.json_tradeoffer.them.assets),
+      created_at,
+      success,
+    }
+  }
+
+  #[test]
+  fn fingerprints_ignore_order() {
+    let (a, b) = (asset("730", "2", "10"), asset("730", "2", "11"));
+
+    assert_eq!(asset_fingerprint(&[a.clone(), b.clone()], &[]), asset_fingerprint(&[b.clone(), a.clone()], &[]));
+    assert_ne!(asset_fingerprint(std::slice::from_ref(&a), std::slice::from_ref(&b)), asset_fingerprint(&[b], &[a]));
+  }
+
+  #[tokio::test]
+  async fn completed_key_returns_its_offer_without_sending() {
+    let mut trade_offer = keyed_offer();
+    let store = MemoryStore::default();
+    store.begin_send_key(&record(&trade_offer, 100, Some(sent("55")))).unwrap();
+
+    // An empty cookie and api key would fail on any request
+    assert_eq!(trade_offer.send_idempotent(&String::new(), &String::new(), "key", &store).await, Ok(sent("55")));
+  }
+
+  #[tokio::test]
+  async fn reused_key_must_match() {
+    let store = MemoryStore::default();
+    store.begin_send_key(&record(&keyed_offer(), 100, Some(sent("55")))).unwrap();
+    let mismatch = Err(IdempotentSendError::KeyMismatch { key: "key".to_string() });
+
+    let mut other_partner = keyed_offer();
+    other_partner.partner = SteamId::from_account_id(33303);
+    assert_eq!(other_partner.send_idempotent(&String::new(), &String::new(), "key", &store).await, mismatch);
+
+    let mut other_assets = keyed_offer();
+    other_assets.add_partner_item(asset("730", "2", "21"));
+    assert_eq!(other_assets.send_idempotent(&String::new(), &String::new(), "key", &store).await, mismatch);
+  }
+
+  #[test]
+  fn pending_key_is_looked_up_before_sending_again() {
+    let trade_offer = keyed_offer();
+    let assets = asset_fingerprint(&trade_offer.json_tradeoffer.me.assets, &trade_offer.json_tradeoffer.them.assets);
+    let store = MemoryStore::default();
+
+    // First attempt begins the key
+    assert_eq!(trade_offer.resume_send_key("key", &assets, &store), Ok(SendKeyStep::New));
+    let created_at = store.get_send_key("key").unwrap().unwrap().created_at;
+
+    // The retry has to check Steam for the offer that attempt may have created
+    assert_eq!(trade_offer.resume_send_key("key", &assets, &store), Ok(SendKeyStep::Pending(created_at)));
+
+    store.complete_send_key("key", &sent("55")).unwrap();
+    assert_eq!(trade_offer.resume_send_key("key", &assets, &store), Ok(SendKeyStep::Sent(sent("55"))));
+  }
+
+  #[test]
+  fn finds_the_offer_a_pending_key_sent() {
+    let trade_offer = keyed_offer();
+    let assets = asset_fingerprint(&trade_offer.json_tradeoffer.me.assets, &trade_offer.json_tradeoffer.them.assets);
+    let since = 10_000;
+
+    let sent_offer = |tradeofferid: &str, give: &str, time_created: u64| {
+      let mut o = offer(vec![offer_item(give, None)], vec![offer_item("20", None)]);
+      o.tradeofferid = tradeofferid.to_string();
+      o.is_our_offer = true;
+      o.time_created = time_created;
+      o.state = TradeOfferState::CreatedNeedsConfirmation;
+      o
+    };
+
+    let mut other_partner = sent_offer("4", "10", since);
+    other_partner.partner = SteamId::from_account_id(33303);
+
+    let offers = vec![
+      sent_offer("1", "11", since),
+      sent_offer("2", "10", since - SEND_KEY_MARGIN_SECS - 1),
+      other_partner,
+      sent_offer("3", "10", since - SEND_KEY_MARGIN_SECS),
+    ];
+
+    let found = trade_offer.find_sent_in(offers, &assets, since).unwrap();
+    assert_eq!((found.tradeofferid.as_str(), found.need_mobile_confirmation), ("3", Some(true)));
+
+    assert_eq!(trade_offer.find_sent_in(vec![sent_offer("1", "11", since)], &assets, since), None);
+  }
+}
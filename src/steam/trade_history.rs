@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use super::Inventory::AssetDescription;
+use super::offers::{self, EconDescription, EconResponse, Offer, OfferError};
+use super::steam_id::SteamId;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TradeStatus {
+  Init,
+  PreCommitted,
+  Committed,
+  Complete,
+  Failed,
+  PartialSupportRollback,
+  FullSupportRollback,
+  SupportRollbackSelective,
+  RollbackFailed,
+  RollbackAbandoned,
+  InEscrow,
+  EscrowRollback,
+  Unknown(i32),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeReceipt {
+  pub tradeid: String,
+  pub partner: SteamId,
+  pub time_init: u64,
+  pub status: TradeStatus,
+  pub assets_given: Vec<TradedAsset>,
+  pub assets_received: Vec<TradedAsset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradedAsset {
+  pub appid: i64,
+  pub contextid: String,
+  pub assetid: String,
+  pub amount: String,
+  pub classid: String,
+  pub instanceid: String,
+  pub new_contextid: Option<String>,
+  pub new_assetid: Option<String>,
+  pub description: Option<AssetDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AssetIdMapping {
+  pub appid: i64,
+  pub old_contextid: String,
+  pub old_assetid: String,
+  pub new_contextid: String,
+  pub new_assetid: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TradeHistory {
+  pub trades: Vec<TradeReceipt>,
+  pub more: bool,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct GetTradeHistoryOptions {
+  pub max_trades: u32,
+  pub start_after_time: Option<u64>,
+  pub start_after_tradeid: Option<String>,
+  pub navigating_back: bool,
+  pub get_descriptions: bool,
+  pub include_failed: bool,
+  pub language: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct GetTradeStatusQuery<'a> {
+  tradeid: &'a String,
+  get_descriptions: bool,
+  language: &'a str,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EconTrades {
+  #[serde(default)]
+  trades: Vec<EconTrade>,
+  #[serde(default)]
+  descriptions: Vec<EconDescription>,
+  #[serde(default)]
+  more: bool,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EconTrade {
+  tradeid: String,
+  steamid_other: SteamId,
+  time_init: u64,
+  status: i32,
+  #[serde(default)]
+  assets_given: Vec<EconTradedAsset>,
+  #[serde(default)]
+  assets_received: Vec<EconTradedAsset>,
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct EconTradedAsset {
+  appid: i64,
+  contextid: String,
+  assetid: String,
+  amount: String,
+  classid: String,
+  instanceid: String,
+  new_contextid: Option<String>,
+  new_assetid: Option<String>,
+}
+
+impl TradeStatus {
+  pub fn from_i32(status: i32) -> TradeStatus {
+    match status {
+      0 => TradeStatus::Init,
+      1 => TradeStatus::PreCommitted,
+      2 => TradeStatus::Committed,
+      3 => TradeStatus::Complete,
+      4 => TradeStatus::Failed,
+      5 => TradeStatus::PartialSupportRollback,
+      6 => TradeStatus::FullSupportRollback,
+      7 => TradeStatus::SupportRollbackSelective,
+      8 => TradeStatus::RollbackFailed,
+      9 => TradeStatus::RollbackAbandoned,
+      10 => TradeStatus::InEscrow,
+      11 => TradeStatus::EscrowRollback,
+      s => TradeStatus::Unknown(s),
+    }
+  }
+}
+
+impl GetTradeHistoryOptions {
+  pub fn new() -> GetTradeHistoryOptions {
+    GetTradeHistoryOptions {
+      max_trades: 100,
+      start_after_time: None,
+      start_after_tradeid: None,
+      navigating_back: false,
+      get_descriptions: true,
+      include_failed: false,
+      language: "english".to_string(),
+    }
+  }
+}
+
+impl TradeReceipt {
+  pub fn given_mapping(&self) -> Vec<AssetIdMapping> {
+    self.assets_given.iter().filter_map(|a| a.mapping()).collect()
+  }
+
+  pub fn received_mapping(&self) -> Vec<AssetIdMapping> {
+    self.assets_received.iter().filter_map(|a| a.mapping()).collect()
+  }
+
+  pub fn asset_mapping(&self) -> Vec<AssetIdMapping> {
+    let mut mapping = self.given_mapping();
+    mapping.extend(self.received_mapping());
+    mapping
+  }
+}
+
+impl TradedAsset {
+  pub fn mapping(&self) -> Option<AssetIdMapping> {
+    let new_assetid = self.new_assetid.to_owned()?;
+
+    Some(AssetIdMapping {
+      appid: self.appid,
+      old_contextid: self.contextid.to_owned(),
+      old_assetid: self.assetid.to_owned(),
+      new_contextid: self.new_contextid.to_owned().unwrap_or(self.contextid.to_owned()),
+      new_assetid,
+    })
+  }
+}
+
+impl Offer {
+  pub async fn get_receipt(&self, api_key: &String) -> Result<Option<TradeReceipt>, OfferError> {
+    match &self.tradeid {
+      Some(tradeid) => get_trade_status(api_key, tradeid, true).await,
+      None => Ok(None)
+    }
+  }
+}
+
+pub async fn get_trade_status(api_key: &String, tradeid: &String, get_descriptions: bool) -> Result<Option<TradeReceipt>, OfferError> {
+  let query = GetTradeStatusQuery { tradeid, get_descriptions, language: "english" };
+
+  let text = offers::econ_get("https://api.steampowered.com/IEconService/GetTradeStatus/v1/", api_key, &query).await?;
+
+  let trades = parse_trades(&text)?;
+
+  Ok(trades.trades.into_iter().next())
+}
+
+pub async fn get_trade_history(api_key: &String, options: &GetTradeHistoryOptions) -> Result<TradeHistory, OfferError> {
+  let text = offers::econ_get("https://api.steampowered.com/IEconService/GetTradeHistory/v1/", api_key, options).await?;
+
+  parse_trades(&text)
+}
+
+fn parse_trades(text: &str) -> Result<TradeHistory, OfferError> {
+  let response = match serde_json::from_str::<EconResponse<EconTrades>>(text) {
+    Ok(res) => res.response,
+    Err(e) => return Err(OfferError::Parse(e.to_string()))
+  };
+
+  let descriptions = offers::index_descriptions(response.descriptions);
+
+  Ok(TradeHistory {
+    trades: response.trades.into_iter().map(|t| t.into_receipt(&descriptions)).collect(),
+    more: response.more,
+  })
+}
+
+impl EconTrade {
+  fn into_receipt(self, descriptions: &HashMap<(i64, String, String), AssetDescription>) -> TradeReceipt {
+    TradeReceipt {
+      tradeid: self.tradeid,
+      partner: self.steamid_other,
+      time_init: self.time_init,
+      status: TradeStatus::from_i32(self.status),
+      assets_given: self.assets_given.into_iter().map(|a| a.into_traded_asset(descriptions)).collect(),
+      assets_received: self.assets_received.into_iter().map(|a| a.into_traded_asset(descriptions)).collect(),
+    }
+  }
+}
+
+impl EconTradedAsset {
+  fn into_traded_asset(self, descriptions: &HashMap<(i64, String, String), AssetDescription>) -> TradedAsset {
+    let description = descriptions.get(&(self.appid, self.classid.to_owned(), self.instanceid.to_owned())).cloned();
+
+    TradedAsset {
+      appid: self.appid,
+      contextid: self.contextid,
+      assetid: self.assetid,
+      amount: self.amount,
+      classid: self.classid,
+      instanceid: self.instanceid,
+      new_contextid: self.new_contextid,
+      new_assetid: self.new_assetid,
+      description,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TRADE_STATUS: &str = r#"{
+    "response": {
+      "trades": [
+        {
+          "tradeid": "3000000009",
+          "steamid_other": "76561197960287930",
+          "time_init": 1699395300,
+          "status": 3,
+          "assets_given": [
+            { "appid": 730, "contextid": "2", "assetid": "111", "amount": "1", "classid": "310776668", "instanceid": "188530139", "new_assetid": "555" }
+          ],
+          "assets_received": [
+            { "appid": 753, "contextid": "6", "assetid": "222", "amount": "3", "classid": "667924416", "instanceid": "0", "new_contextid": "6", "new_assetid": "666" },
+            { "appid": 730, "contextid": "2", "assetid": "333", "amount": "1", "classid": "999", "instanceid": "0" }
+          ]
+        }
+      ],
+      "descriptions": [
+        { "appid": 730, "classid": "310776668", "instanceid": "188530139", "tradable": true, "market_hash_name": "AK-47 | Redline (Field-Tested)" },
+        { "appid": 753, "classid": "667924416", "instanceid": "0", "tradable": true, "market_hash_name": "753-Gems" }
+      ]
+    }
+  }"#;
+
+  #[test]
+  fn parses_trade_status() {
+    let history = parse_trades(TRADE_STATUS).unwrap();
+    assert!(!history.more);
+
+    let receipt = &history.trades[0];
+    assert_eq!(receipt.tradeid, "3000000009");
+    assert_eq!(receipt.partner, SteamId::from_account_id(22202));
+    assert_eq!(receipt.status, TradeStatus::Complete);
+    assert_eq!(receipt.assets_given.len(), 1);
+    assert_eq!(receipt.assets_received.len(), 2);
+    assert_eq!(receipt.assets_received[0].amount, "3");
+  }
+
+  #[test]
+  fn joins_descriptions() {
+    let receipt = parse_trades(TRADE_STATUS).unwrap().trades.remove(0);
+
+    assert_eq!(receipt.assets_given[0].description.as_ref().map(|d| d.market_hash_name.as_str()), Some("AK-47 | Redline (Field-Tested)"));
+    assert_eq!(receipt.assets_received[0].description.as_ref().map(|d| d.market_hash_name.as_str()), Some("753-Gems"));
+    assert_eq!(receipt.assets_received[1].description, None);
+  }
+
+  #[test]
+  fn maps_old_to_new_asset_ids() {
+    let receipt = parse_trades(TRADE_STATUS).unwrap().trades.remove(0);
+
+    // No new_contextid, the asset stays in its old context
+    assert_eq!(receipt.given_mapping(), vec![AssetIdMapping {
+      appid: 730,
+      old_contextid: "2".to_string(),
+      old_assetid: "111".to_string(),
+      new_contextid: "2".to_string(),
+      new_assetid: "555".to_string(),
+    }]);
+
+    // Asset 333 has no new_assetid yet and is skipped
+    assert_eq!(receipt.received_mapping(), vec![AssetIdMapping {
+      appid: 753,
+      old_contextid: "6".to_string(),
+      old_assetid: "222".to_string(),
+      new_contextid: "6".to_string(),
+      new_assetid: "666".to_string(),
+    }]);
+
+    assert_eq!(receipt.asset_mapping().iter().map(|m| m.old_assetid.as_str()).collect::<Vec<_>>(), vec!["111", "222"]);
+  }
+
+  #[test]
+  fn parses_trade_history_page() {
+    let history = parse_trades(r#"{
+      "response": {
+        "more": true,
+        "trades": [
+          { "tradeid": "1", "steamid_other": "76561197960287930", "time_init": 10, "status": 12 },
+          { "tradeid": "2", "steamid_other": "76561197960287930", "time_init": 5, "status": 10 }
+        ]
+      }
+    }"#).unwrap();
+
+    assert!(history.more);
+    assert_eq!(history.trades[0].status, TradeStatus::Unknown(12));
+    assert_eq!(history.trades[1].status, TradeStatus::InEscrow);
+    assert!(history.trades[0].asset_mapping().is_empty());
+
+    assert_eq!(parse_trades(r#"{ "response": {} }"#), Ok(TradeHistory { trades: Vec::new(), more: false }));
+    assert!(matches!(parse_trades("not json"), Err(OfferError::Parse(_))));
+  }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use reqwest::{Client, StatusCode};
+use regex::Regex;
+use super::account::Account;
+use super::Inventory::UnauthorizedResponse;
+use super::steam_id::SteamId;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TradeUrl {
+  pub partner: u32,
+  pub token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TradeUrlError {
+  InvalidUrl(String),
+  MissingPartner,
+  InvalidPartner(String),
+  MissingToken,
+  // Parsed fine but does not point at steamcommunity.com/tradeoffer/new/
+  NotTradeOfferUrl(String),
+  // The privacy page loaded but had no trade url in it
+  NotFound,
+  Parse(String),
+  Unauthorized(UnauthorizedResponse),
+  // No response or no body, usually a timeout
+  Network(String),
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct NewTradeUrlForm {
+  sessionid: String,
+}
+
+impl TradeUrl {
+  pub fn new(partner: u32, token: Option<String>) -> TradeUrl {
+    TradeUrl { partner, token }
+  }
+
+  pub fn from_steam_id(steam_id: SteamId, token: Option<String>) -> TradeUrl {
+    TradeUrl { partner: steam_id.account_id(), token }
+  }
+
+  pub fn parse(trade_url: &str) -> Result<TradeUrl, TradeUrlError> {
+    let url = match reqwest::Url::parse(trade_url) {
+      Ok(url) => url,
+      Err(e) => return Err(TradeUrlError::InvalidUrl(e.to_string()))
+    };
+
+    let is_steam = matches!(url.host_str(), Some("steamcommunity.com") | Some("www.steamcommunity.com"));
+    let is_new_offer = url.path().trim_end_matches('/') == "/tradeoffer/new";
+
+    if !is_steam || !is_new_offer || !matches!(url.scheme(), "http" | "https") {
+      return Err(TradeUrlError::NotTradeOfferUrl(trade_url.to_string()));
+    }
+
+    let parsed_url: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+    let partner = match parsed_url.get("partner") {
+      Some(p) => p,
+      None => return Err(TradeUrlError::MissingPartner)
+    };
+
+    let partner = match partner.parse::<u32>() {
+      Ok(p) => p,
+      Err(_) => return Err(TradeUrlError::InvalidPartner(partner.to_owned()))
+    };
+
+    let token = parsed_url.get("token").filter(|t| !t.is_empty()).cloned();
+
+    Ok(TradeUrl { partner, token })
+  }
+
+  pub fn steam_id(&self) -> SteamId {
+    SteamId::from_account_id(self.partner)
+  }
+
+  pub async fn fetch(account: &Account) -> Result<TradeUrl, TradeUrlError> {
+    let url = format!("https://steamcommunity.com/profiles/{}/tradeoffers/privacy", account.steam_id);
+
+    let client = Client::new();
+    let res = match client.get(url).header("Cookie", &account.cookie).send().await {
+      Ok(res) => res,
+      Err(e) => return Err(TradeUrlError::Network(e.to_string()))
+    };
+
+    let status = res.status().to_owned();
+    let text = match res.text().await {
+      Ok(text) => text,
+      Err(e) => return Err(TradeUrlError::Network(e.to_string()))
+    };
+
+    match status {
+      StatusCode::OK => (),
+      _ => return Err(TradeUrlError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }))
+    }
+
+    let re = Regex::new(r#"id="trade_offer_access_url"[^>]*value="([^"]+)""#).unwrap();
+    let trade_url = match re.captures(&text) {
+      Some(c) => c[1].replace("&amp;", "&"),
+      None => return Err(TradeUrlError::NotFound)
+    };
+
+    TradeUrl::parse(&trade_url)
+  }
+
+  pub async fn regenerate(account: &Account) -> Result<TradeUrl, TradeUrlError> {
+    let url = format!("https://steamcommunity.com/profiles/{}/tradeoffers/newtradeurl", account.steam_id);
+    let referer = format!("https://steamcommunity.com/profiles/{}/tradeoffers/privacy", account.steam_id);
+
+    let form_data = NewTradeUrlForm { sessionid: super::create_session_id() };
+    let cookie = format!("{}sessionid={};", account.cookie, &form_data.sessionid);
+
+    let client = Client::new();
+    let res = match client.post(url).header("Referer", referer).header("Cookie", cookie).form(&form_data).send().await {
+      Ok(res) => res,
+      Err(e) => return Err(TradeUrlError::Network(e.to_string()))
+    };
+
+    let status = res.status().to_owned();
+    let text = match res.text().await {
+      Ok(text) => text,
+      Err(e) => return Err(TradeUrlError::Network(e.to_string()))
+    };
+
+    match status {
+      StatusCode::OK => (),
+      _ => return Err(TradeUrlError::Unauthorized(UnauthorizedResponse { status: status.to_string(), error: text }))
+    }
+
+    // Steam responds with the new token as a bare json string
+    let token = match serde_json::from_str::<String>(&text) {
+      Ok(token) => token,
+      Err(e) => return Err(TradeUrlError::Parse(e.to_string()))
+    };
+
+    Ok(TradeUrl::from_steam_id(account.steam_id, Some(token)))
+  }
+}
+
+impl fmt::Display for TradeUrl {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self.token {
+      Some(token) => write!(f, "https://steamcommunity.com/tradeoffer/new/?partner={}&token={}", self.partner, token),
+      None => write!(f, "https://steamcommunity.com/tradeoffer/new/?partner={}", self.partner),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use proptest::prelude::*;
+
+  #[test]
+  fn parses_trade_urls() {
+    let url = TradeUrl::parse("https://steamcommunity.com/tradeoffer/new/?partner=22202&token=abcDEF12").unwrap();
+    assert_eq!(url, TradeUrl::new(22202, Some("abcDEF12".to_string())));
+    assert_eq!(url.steam_id(), SteamId::from_steam64(76561197960287930));
+
+    let friend = TradeUrl::parse("https://steamcommunity.com/tradeoffer/new?partner=22202").unwrap();
+    assert_eq!(friend.token, None);
+  }
+
+  #[test]
+  fn rejects_non_trade_urls() {
+    assert_eq!(TradeUrl::parse("https://evil.example/?partner=1&token=x"), Err(TradeUrlError::NotTradeOfferUrl("https://evil.example/?partner=1&token=x".to_string())));
+    assert!(matches!(TradeUrl::parse("https://steamcommunity.com/id/someone/?partner=1"), Err(TradeUrlError::NotTradeOfferUrl(_))));
+    assert!(matches!(TradeUrl::parse("https://steamcommunity.com.evil.example/tradeoffer/new/?partner=1"), Err(TradeUrlError::NotTradeOfferUrl(_))));
+    assert_eq!(TradeUrl::parse("https://steamcommunity.com/tradeoffer/new/?token=x"), Err(TradeUrlError::MissingPartner));
+    assert!(matches!(TradeUrl::parse("https://steamcommunity.com/tradeoffer/new/?partner=abc"), Err(TradeUrlError::InvalidPartner(_))));
+    assert!(matches!(TradeUrl::parse("not a url"), Err(TradeUrlError::InvalidUrl(_))));
+  }
+
+  proptest! {
+    #[test]
+    fn round_trips_through_steam_id(account_id in any::<u32>(), token in proptest::option::of("[A-Za-z0-9_-]{1,16}")) {
+      let steam_id = SteamId::from_account_id(account_id);
+      let trade_url = TradeUrl::from_steam_id(steam_id, token.to_owned());
+
+      prop_assert_eq!(trade_url.steam_id(), steam_id);
+
+      let parsed = TradeUrl::parse(&trade_url.to_string()).unwrap();
+      prop_assert_eq!(parsed.steam_id(), steam_id);
+      prop_assert_eq!(parsed, trade_url);
+    }
+  }
+}
@@ -0,0 +1,91 @@
+use crate::steam::Inventory::{Asset, AssetDescription, Inventory, Tag};
+use crate::steam::offers::{Offer, OfferItem};
+use crate::steam::offer_state::TradeOfferState;
+use crate::steam::steam_id::SteamId;
+
+// Tags are given as (category, tag name) pairs
+pub fn description(classid: &str, market_hash_name: &str, tags: &[(&str, &str)]) -> AssetDescription {
+  AssetDescription {
+    appid: 730,
+    classid: classid.to_string(),
+    instanceid: "0".to_string(),
+    currency: 0,
+    background_color: String::new(),
+    icon_url: String::new(),
+    icon_url_large: None,
+    descriptions: Vec::new(),
+    tradable: 1,
+    actions: None,
+    name: market_hash_name.to_string(),
+    name_color: None,
+    _type: String::new(),
+    market_name: market_hash_name.to_string(),
+    market_hash_name: market_hash_name.to_string(),
+    market_actions: None,
+    commodity: 0,
+    market_tradable_restriction: 7,
+    market_marketable_restriction: None,
+    marketable: 1,
+    tags: tags.iter().map(|(category, tag)| Tag {
+      category: category.to_string(),
+      internal_name: tag.to_string(),
+      localized_category_name: category.to_string(),
+      localized_tag_name: tag.to_string(),
+      color: None,
+    }).collect(),
+    market_buy_country_restriction: None,
+  }
+}
+
+pub fn offer_item(assetid: &str, description: Option<AssetDescription>) -> OfferItem {
+  OfferItem {
+    appid: 730,
+    contextid: "2".to_string(),
+    assetid: assetid.to_string(),
+    classid: description.as_ref().map(|d| d.classid.to_owned()).unwrap_or("0".to_string()),
+    instanceid: "0".to_string(),
+    amount: "1".to_string(),
+    missing: false,
+    description,
+  }
+}
+
+pub fn offer(give: Vec<OfferItem>, receive: Vec<OfferItem>) -> Offer {
+  Offer {
+    tradeofferid: "1".to_string(),
+    tradeid: None,
+    partner: SteamId::from_account_id(22202),
+    accountid_other: 22202,
+    message: String::new(),
+    state: TradeOfferState::Active,
+    is_our_offer: false,
+    items_to_give: give,
+    items_to_receive: receive,
+    time_created: 0,
+    time_updated: 0,
+    expiration_time: 0,
+    escrow_end_date: 0,
+    from_real_time_trade: false,
+    confirmation_method: 0,
+  }
+}
+
+// Each item is an (assetid, description) pair, the asset shares the description's classid
+pub fn inventory(items: Vec<(&str, AssetDescription)>) -> Inventory {
+  let assets = items.iter().map(|(assetid, d)| Asset {
+    appid: d.appid,
+    contextid: "2".to_string(),
+    assetid: assetid.to_string(),
+    classid: d.classid.to_owned(),
+    instanceid: d.instanceid.to_owned(),
+    amount: "1".to_string(),
+  }).collect();
+
+  Inventory {
+    assets,
+    descriptions: items.into_iter().map(|(_, d)| d).collect(),
+    total_inventory_count: 0,
+    success: 1,
+    rwgrsn: 0,
+  }
+}